@@ -0,0 +1,738 @@
+use crate::path_ext::confine_to_base;
+use crate::zip_extractor::extract_prefix_from_zip;
+use flate2::read::GzDecoder;
+use glob::Pattern;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use tar::{Archive, EntryType};
+use xz2::read::XzDecoder;
+
+/// Формат архива, определяется по расширению файла.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    TarXz,
+    TarGz,
+    Tar,
+}
+
+impl ArchiveKind {
+    /// Определяет формат по имени файла ("x.tar.xz") или по самому расширению ("tar.xz").
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = format!(".{}", name.to_lowercase());
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            Some(ArchiveKind::TarXz)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// Ограничения против архивов-бомб. Превышение любого из них прерывает распаковку с ошибкой.
+#[derive(Debug, Clone)]
+pub struct ExtractLimits {
+    /// Максимальное число записей в архиве (включая отфильтрованные)
+    pub max_entries: usize,
+    /// Максимальный распакованный размер одного файла
+    pub max_file_size: u64,
+    /// Максимальный суммарный размер извлечённых файлов
+    pub max_total_size: u64,
+    /// Максимальное отношение распакованного размера к сжатому
+    pub max_ratio: u64,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        ExtractLimits {
+            max_entries: 10_000,
+            max_file_size: 1 << 30,
+            max_total_size: 2 << 30,
+            max_ratio: 100,
+        }
+    }
+}
+
+/// Какие записи архива извлекать и куда.
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    /// Извлекаются только записи, путь которых начинается с prefix; сам префикс отбрасывается.
+    /// Ожидается с forward-slashes и без ведущего слэша, например "ffmpeg-master-latest-linux64-lgpl/bin/"
+    pub prefix: String,
+    /// Сколько ведущих компонентов пути отбросить после prefix (как `tar --strip-components`)
+    pub strip_components: usize,
+    /// Если не пусто — извлекаются только файлы, чей путь (после prefix и strip_components)
+    /// подходит хотя бы под один шаблон
+    pub include: Vec<Pattern>,
+    /// Класть файлы прямо в каталог назначения по имени, без промежуточных каталогов
+    pub flatten: bool,
+    pub limits: ExtractLimits,
+}
+
+/// Почему запись архива не была извлечена.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Абсолютный путь, '..' или префикс диска — запись вышла бы за пределы каталога назначения
+    UnsafePath,
+    /// Символическая или жёсткая ссылка
+    Link,
+    /// Устройство, FIFO и прочие специальные записи
+    UnsupportedType,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedEntry {
+    pub name: String,
+    pub reason: SkipReason,
+}
+
+/// Итог распаковки: что извлечено и что пропущено с причиной.
+/// Записи, не прошедшие фильтр prefix/include, только подсчитываются.
+#[derive(Debug, Default, Serialize)]
+pub struct ExtractReport {
+    pub extracted: Vec<PathBuf>,
+    pub skipped: Vec<SkippedEntry>,
+    pub filtered: usize,
+    pub total_bytes: u64,
+}
+
+/// Куда попадёт запись архива.
+enum EntryTarget {
+    /// Путь относительно каталога назначения
+    Extract(PathBuf),
+    /// Не прошла фильтр prefix/strip_components/include
+    Filtered,
+    /// Путь выходит за пределы каталога назначения
+    Unsafe,
+}
+
+/// Имя записи безопасно, если это относительный путь только из обычных компонентов
+/// (семантика enclosed_name: без '..', корня и префикса диска).
+fn is_enclosed(name: &str) -> bool {
+    !name.contains('\0')
+        && !name.starts_with('/')
+        && Path::new(name)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        && !name
+            .split('/')
+            .any(|part| part == ".." || (part.len() >= 2 && part.as_bytes()[1] == b':'))
+}
+
+impl ExtractOptions {
+    /// Определяет, куда извлекать запись `name`.
+    fn target_path(&self, name: &str, is_dir: bool) -> EntryTarget {
+        let name = name.replace('\\', "/");
+        if !is_enclosed(&name) {
+            return EntryTarget::Unsafe;
+        }
+        let Some(rel) = name.strip_prefix(&self.prefix) else {
+            return EntryTarget::Filtered;
+        };
+        let parts: Vec<&str> = rel
+            .split('/')
+            .filter(|part| !part.is_empty() && *part != ".")
+            .skip(self.strip_components)
+            .collect();
+        if parts.is_empty() {
+            return EntryTarget::Filtered;
+        }
+        let rel = parts.join("/");
+        // каталоги при фильтре по шаблонам не создаём: нужные появятся вместе с файлами
+        if !self.include.is_empty() && (is_dir || !self.include.iter().any(|p| p.matches(&rel))) {
+            return EntryTarget::Filtered;
+        }
+        if self.flatten {
+            return match (is_dir, parts.last()) {
+                (false, Some(file_name)) => EntryTarget::Extract(PathBuf::from(file_name)),
+                _ => EntryTarget::Filtered,
+            };
+        }
+        EntryTarget::Extract(PathBuf::from(rel))
+    }
+}
+
+fn limit_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Общий учёт записей и лимитов для всех форматов.
+pub(crate) struct Extraction<'a> {
+    dest: &'a Path,
+    options: &'a ExtractOptions,
+    /// Размер архива на диске — база для проверки коэффициента сжатия
+    archive_size: u64,
+    entries: usize,
+    /// Каталоги, созданные распаковкой (для отката при ошибке)
+    created_dirs: Vec<PathBuf>,
+    pub(crate) report: ExtractReport,
+}
+
+impl<'a> Extraction<'a> {
+    pub(crate) fn new(
+        archive: &Path,
+        dest: &'a Path,
+        options: &'a ExtractOptions,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(dest)?;
+        Ok(Extraction {
+            dest,
+            options,
+            archive_size: fs::metadata(archive)?.len(),
+            entries: 0,
+            created_dirs: Vec::new(),
+            report: ExtractReport::default(),
+        })
+    }
+
+    /// Учитывает очередную запись архива; ошибка, если записей слишком много.
+    pub(crate) fn count_entry(&mut self) -> io::Result<()> {
+        self.entries += 1;
+        if self.entries > self.options.limits.max_entries {
+            return Err(limit_error(format!(
+                "archive has more than {} entries",
+                self.options.limits.max_entries
+            )));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn skip(&mut self, name: &str, reason: SkipReason) {
+        self.report.skipped.push(SkippedEntry {
+            name: name.to_string(),
+            reason,
+        });
+    }
+
+    /// Выбирает путь для записи; пропущенные записи сразу попадают в отчёт.
+    pub(crate) fn select(&mut self, name: &str, is_dir: bool) -> Option<PathBuf> {
+        match self.options.target_path(name, is_dir) {
+            EntryTarget::Extract(rel) => Some(rel),
+            EntryTarget::Filtered => {
+                self.report.filtered += 1;
+                None
+            }
+            EntryTarget::Unsafe => {
+                self.skip(name, SkipReason::UnsafePath);
+                None
+            }
+        }
+    }
+
+    /// Запоминает ещё не существующие каталоги от `dir` вверх до dest.
+    fn track_new_dirs(&mut self, dir: &Path) {
+        let mut dir = self.dest.join(dir);
+        while dir != self.dest && dir.starts_with(self.dest) && !dir.exists() {
+            self.created_dirs.push(dir.clone());
+            if !dir.pop() {
+                break;
+            }
+        }
+    }
+
+    /// Создаёт каталог из архива внутри dest.
+    pub(crate) fn create_dir(&mut self, rel: &Path) -> io::Result<()> {
+        self.track_new_dirs(rel);
+        let path = confine_to_base(self.dest, rel)?;
+        fs::create_dir_all(path)
+    }
+
+    /// Завершает распаковку: при ошибке удаляет всё, что успело извлечься,
+    /// чтобы в dest не осталось частично распакованного архива.
+    pub(crate) fn finish(mut self, result: io::Result<()>) -> io::Result<ExtractReport> {
+        if let Err(e) = result {
+            for rel in &self.report.extracted {
+                let _ = fs::remove_file(self.dest.join(rel));
+            }
+            // сначала вложенные каталоги; непустые (с чужими файлами) остаются
+            self.created_dirs
+                .sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+            for dir in &self.created_dirs {
+                let _ = fs::remove_dir(dir);
+            }
+            return Err(e);
+        }
+        Ok(self.report)
+    }
+
+    /// Записывает файл внутри dest, не доверяя размеру из заголовка:
+    /// копирование обрывается, как только превышен лимит на файл, сумму или коэффициент сжатия.
+    pub(crate) fn write_file(
+        &mut self,
+        rel: &Path,
+        reader: &mut impl Read,
+        mode: Option<u32>,
+    ) -> io::Result<()> {
+        if let Some(parent) = rel.parent() {
+            self.track_new_dirs(parent);
+        }
+        let limits = &self.options.limits;
+        let path = confine_to_base(self.dest, rel)?;
+        // существующую ссылку заменяем файлом, а не пишем сквозь неё
+        if path
+            .symlink_metadata()
+            .is_ok_and(|m| m.file_type().is_symlink())
+        {
+            fs::remove_file(&path)?;
+        }
+
+        let budget = limits.max_file_size.min(
+            limits
+                .max_total_size
+                .saturating_sub(self.report.total_bytes),
+        );
+        let mut outfile = File::create(&path)?;
+        let written = io::copy(&mut reader.take(budget.saturating_add(1)), &mut outfile)?;
+        if written > budget {
+            drop(outfile);
+            let _ = fs::remove_file(&path);
+            return Err(limit_error(format!(
+                "{} exceeds the size limit ({} bytes per file, {} bytes in total)",
+                rel.display(),
+                limits.max_file_size,
+                limits.max_total_size
+            )));
+        }
+        self.report.total_bytes += written;
+        if self.report.total_bytes > self.archive_size.max(1).saturating_mul(limits.max_ratio) {
+            let _ = fs::remove_file(&path);
+            return Err(limit_error(format!(
+                "archive expands more than {}x, refusing to continue",
+                limits.max_ratio
+            )));
+        }
+
+        apply_mode(&path, mode)?;
+        self.report.extracted.push(rel.to_path_buf());
+        Ok(())
+    }
+}
+
+/// Выставляет unix-права, сохранённые в архиве (без setuid/setgid/sticky).
+#[cfg(unix)]
+pub(crate) fn apply_mode(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    if let Some(mode) = mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777))?;
+    }
+    Ok(())
+}
+
+/// На других ОС права из архива не применяются.
+#[cfg(not(unix))]
+pub(crate) fn apply_mode(_path: &Path, _mode: Option<u32>) -> io::Result<()> {
+    Ok(())
+}
+
+/// Извлекает архив `archive` формата `kind` (zip, tar.xz, tar.gz, tar) в каталог `dest`.
+/// Формат задаётся явно: имя скачанного файла не всегда говорит о нём. Отбор записей задаётся `options`.
+///
+/// Записи с небезопасными путями, ссылки и специальные файлы пропускаются и попадают в отчёт;
+/// превышение ExtractLimits прерывает распаковку с ошибкой InvalidData, а уже извлечённое удаляется.
+pub fn extract_archive(
+    kind: ArchiveKind,
+    archive: &Path,
+    dest: &Path,
+    options: &ExtractOptions,
+) -> io::Result<ExtractReport> {
+    match kind {
+        ArchiveKind::Zip => {
+            extract_prefix_from_zip(archive, dest, options).map_err(io::Error::from)
+        }
+        ArchiveKind::TarXz => {
+            let reader = XzDecoder::new(File::open(archive)?);
+            extract_tar(reader, Extraction::new(archive, dest, options)?)
+        }
+        ArchiveKind::TarGz => {
+            let reader = GzDecoder::new(File::open(archive)?);
+            extract_tar(reader, Extraction::new(archive, dest, options)?)
+        }
+        ArchiveKind::Tar => {
+            let reader = File::open(archive)?;
+            extract_tar(reader, Extraction::new(archive, dest, options)?)
+        }
+    }
+}
+
+/// Извлекает обычные файлы и каталоги из tar-потока.
+fn extract_tar<R: Read>(reader: R, mut extraction: Extraction) -> io::Result<ExtractReport> {
+    let result = extract_tar_entries(reader, &mut extraction);
+    extraction.finish(result)
+}
+
+fn extract_tar_entries<R: Read>(reader: R, extraction: &mut Extraction) -> io::Result<()> {
+    let mut archive = Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        extraction.count_entry()?;
+
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let entry_type = entry.header().entry_type();
+        let is_dir = entry_type == EntryType::Directory;
+        if entry_type.is_symlink() || entry_type.is_hard_link() {
+            extraction.skip(&name, SkipReason::Link);
+            continue;
+        }
+        if !is_dir && !entry_type.is_file() {
+            // служебные заголовки pax/gnu tar разбирает сам, сюда попадают устройства, FIFO и т.п.
+            extraction.skip(&name, SkipReason::UnsupportedType);
+            continue;
+        }
+        let Some(rel_path) = extraction.select(&name, is_dir) else {
+            continue;
+        };
+
+        if is_dir {
+            extraction.create_dir(&rel_path)?;
+        } else {
+            let mode = entry.header().mode().ok();
+            extraction.write_file(&rel_path, &mut entry, mode)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+    use xz2::write::XzEncoder;
+    use zip::write::SimpleFileOptions;
+
+    enum Entry<'a> {
+        File(&'a str, Vec<u8>),
+        Dir(&'a str),
+        Symlink(&'a str, &'a str),
+        HardLink(&'a str, &'a str),
+    }
+
+    /// Заголовок tar с именем как есть: Header::set_path отказывается писать '..' и корень.
+    fn tar_header(name: &str, link: &str, entry_type: EntryType, size: u64) -> tar::Header {
+        let mut header = tar::Header::new_gnu();
+        let old = header.as_old_mut();
+        old.name[..name.len()].copy_from_slice(name.as_bytes());
+        old.linkname[..link.len()].copy_from_slice(link.as_bytes());
+        header.set_entry_type(entry_type);
+        header.set_size(size);
+        header.set_mode(0o755);
+        header.set_cksum();
+        header
+    }
+
+    fn tar_bytes(entries: &[Entry]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for entry in entries {
+            let (header, data): (_, &[u8]) = match entry {
+                Entry::File(name, data) => (
+                    tar_header(name, "", EntryType::Regular, data.len() as u64),
+                    data,
+                ),
+                Entry::Dir(name) => (tar_header(name, "", EntryType::Directory, 0), &[]),
+                Entry::Symlink(name, target) => {
+                    (tar_header(name, target, EntryType::Symlink, 0), &[])
+                }
+                Entry::HardLink(name, target) => {
+                    (tar_header(name, target, EntryType::Link, 0), &[])
+                }
+            };
+            builder.append(&header, data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn write_tar_gz(path: &Path, entries: &[Entry]) {
+        let mut encoder = GzEncoder::new(File::create(path).unwrap(), Compression::best());
+        encoder.write_all(&tar_bytes(entries)).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    fn write_tar_xz(path: &Path, entries: &[Entry]) {
+        let mut encoder = XzEncoder::new(File::create(path).unwrap(), 6);
+        encoder.write_all(&tar_bytes(entries)).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    fn write_zip(path: &Path, entries: &[Entry]) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        let options = SimpleFileOptions::default();
+        for entry in entries {
+            match entry {
+                Entry::File(name, data) => {
+                    zip.start_file(*name, options).unwrap();
+                    zip.write_all(data).unwrap();
+                }
+                Entry::Dir(name) => zip.add_directory(*name, options).unwrap(),
+                Entry::Symlink(name, target) | Entry::HardLink(name, target) => {
+                    zip.add_symlink(*name, *target, options).unwrap()
+                }
+            }
+        }
+        zip.finish().unwrap();
+    }
+
+    /// Все файлы и каталоги под `dir` относительными путями, по алфавиту.
+    fn tree(dir: &Path) -> Vec<String> {
+        fn walk(root: &Path, dir: &Path, out: &mut Vec<String>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                let rel = path.strip_prefix(root).unwrap().to_string_lossy();
+                out.push(rel.replace('\\', "/"));
+                if path.is_dir() && !path.is_symlink() {
+                    walk(root, &path, out);
+                }
+            }
+        }
+        let mut out = Vec::new();
+        walk(dir, dir, &mut out);
+        out.sort();
+        out
+    }
+
+    fn paths(report: &ExtractReport) -> Vec<String> {
+        let mut paths: Vec<String> = report
+            .extracted
+            .iter()
+            .map(|p| p.to_string_lossy().replace('\\', "/"))
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    fn skipped(report: &ExtractReport) -> Vec<(&str, SkipReason)> {
+        report
+            .skipped
+            .iter()
+            .map(|s| (s.name.as_str(), s.reason))
+            .collect()
+    }
+
+    fn malicious_entries() -> Vec<Entry<'static>> {
+        vec![
+            Entry::Dir("tool/"),
+            Entry::File("tool/bin/ffmpeg", b"binary".to_vec()),
+            Entry::File("../evil", b"evil".to_vec()),
+            Entry::File("tool/../../evil2", b"evil".to_vec()),
+            Entry::File("/abs-evil", b"evil".to_vec()),
+            Entry::File("C:/drive-evil", b"evil".to_vec()),
+            Entry::Symlink("tool/link", "/etc/passwd"),
+            Entry::HardLink("tool/hard", "../evil"),
+        ]
+    }
+
+    #[test]
+    fn is_enclosed_accepts_only_relative_normal_paths() {
+        for name in ["tool/bin/ffmpeg", "./tool/ffmpeg", "ffmpeg", "tool/"] {
+            assert!(is_enclosed(name), "{}", name);
+        }
+        for name in [
+            "../evil",
+            "tool/../../evil",
+            "tool/..",
+            "/etc/passwd",
+            "C:/Windows/evil",
+            "tool/C:evil",
+            "nul\0byte",
+        ] {
+            assert!(!is_enclosed(name), "{}", name);
+        }
+        let options = ExtractOptions::default();
+        assert!(matches!(
+            options.target_path("tool\\..\\..\\evil", false),
+            EntryTarget::Unsafe
+        ));
+    }
+
+    #[test]
+    fn tar_gz_skips_unsafe_paths_and_links() {
+        let dir = tempfile::tempdir().unwrap();
+        let (archive, dest) = (dir.path().join("tool.tar.gz"), dir.path().join("dest"));
+        write_tar_gz(&archive, &malicious_entries());
+
+        let report = extract_archive(
+            ArchiveKind::TarGz,
+            &archive,
+            &dest,
+            &ExtractOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(paths(&report), ["tool/bin/ffmpeg"]);
+        assert_eq!(
+            skipped(&report),
+            [
+                ("../evil", SkipReason::UnsafePath),
+                ("tool/../../evil2", SkipReason::UnsafePath),
+                ("/abs-evil", SkipReason::UnsafePath),
+                ("C:/drive-evil", SkipReason::UnsafePath),
+                ("tool/link", SkipReason::Link),
+                ("tool/hard", SkipReason::Link),
+            ]
+        );
+        assert_eq!(tree(&dest), ["tool", "tool/bin", "tool/bin/ffmpeg"]);
+        assert_eq!(tree(dir.path()).len(), 5, "{:?}", tree(dir.path()));
+        assert!(!Path::new("/abs-evil").exists());
+    }
+
+    #[test]
+    fn zip_skips_unsafe_paths_and_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (archive, dest) = (dir.path().join("tool.zip"), dir.path().join("dest"));
+        write_zip(&archive, &malicious_entries());
+
+        let report = extract_archive(
+            ArchiveKind::Zip,
+            &archive,
+            &dest,
+            &ExtractOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(paths(&report), ["tool/bin/ffmpeg"]);
+        assert!(report.skipped.iter().all(|s| s.name != "tool/bin/ffmpeg"));
+        assert_eq!(report.skipped.len(), 6);
+        assert_eq!(tree(&dest), ["tool", "tool/bin", "tool/bin/ffmpeg"]);
+        assert_eq!(tree(dir.path()).len(), 5);
+    }
+
+    #[test]
+    fn tar_xz_strips_components_filters_and_flattens() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("ffmpeg.tar.xz");
+        write_tar_xz(
+            &archive,
+            &[
+                Entry::Dir("ffmpeg-7.1/"),
+                Entry::File("ffmpeg-7.1/bin/ffmpeg", b"ffmpeg".to_vec()),
+                Entry::File("ffmpeg-7.1/bin/ffprobe", b"ffprobe".to_vec()),
+                Entry::File("ffmpeg-7.1/doc/README", b"readme".to_vec()),
+                Entry::File("other/NOTICE", b"other".to_vec()),
+            ],
+        );
+
+        let stripped = ExtractOptions {
+            strip_components: 1,
+            ..ExtractOptions::default()
+        };
+        let dest = dir.path().join("stripped");
+        let report = extract_archive(ArchiveKind::TarXz, &archive, &dest, &stripped).unwrap();
+        assert_eq!(
+            paths(&report),
+            ["NOTICE", "bin/ffmpeg", "bin/ffprobe", "doc/README"]
+        );
+
+        let flat = ExtractOptions {
+            prefix: "ffmpeg-7.1/".to_string(),
+            include: vec![Pattern::new("bin/*").unwrap()],
+            flatten: true,
+            ..ExtractOptions::default()
+        };
+        let dest = dir.path().join("flat");
+        let report = extract_archive(ArchiveKind::TarXz, &archive, &dest, &flat).unwrap();
+        assert_eq!(paths(&report), ["ffmpeg", "ffprobe"]);
+        assert_eq!(report.filtered, 3);
+        assert_eq!(tree(&dest), ["ffmpeg", "ffprobe"]);
+        assert_eq!(fs::read(dest.join("ffmpeg")).unwrap(), b"ffmpeg");
+    }
+
+    /// Распаковка с лимитами `limits` должна завершиться InvalidData и ничего не оставить в dest.
+    fn assert_limit_error(kind: ArchiveKind, archive: &Path, limits: ExtractLimits) {
+        let dest = archive.with_extension("out");
+        let options = ExtractOptions {
+            limits,
+            ..ExtractOptions::default()
+        };
+        let error = extract_archive(kind, archive, &dest, &options).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{}", error);
+        assert!(tree(&dest).is_empty(), "{:?}", tree(&dest));
+    }
+
+    #[test]
+    fn limits_abort_without_partial_extraction() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("files.tar.gz");
+        write_tar_gz(
+            &archive,
+            &[
+                Entry::File("tool/a", vec![b'a'; 40]),
+                Entry::File("tool/sub/b", vec![b'b'; 40]),
+                Entry::File("tool/c", vec![b'c'; 100]),
+            ],
+        );
+        let defaults = ExtractLimits::default();
+        assert_limit_error(
+            ArchiveKind::TarGz,
+            &archive,
+            ExtractLimits {
+                max_entries: 2,
+                ..defaults.clone()
+            },
+        );
+        assert_limit_error(
+            ArchiveKind::TarGz,
+            &archive,
+            ExtractLimits {
+                max_file_size: 50,
+                ..defaults.clone()
+            },
+        );
+        assert_limit_error(
+            ArchiveKind::TarGz,
+            &archive,
+            ExtractLimits {
+                max_total_size: 100,
+                ..defaults.clone()
+            },
+        );
+
+        let bomb = dir.path().join("bomb.tar.gz");
+        write_tar_gz(
+            &bomb,
+            &[
+                Entry::File("tool/a", b"small".to_vec()),
+                Entry::File("tool/zeros", vec![0; 1 << 20]),
+            ],
+        );
+        assert_limit_error(ArchiveKind::TarGz, &bomb, defaults.clone());
+    }
+
+    #[test]
+    fn zip_limits_abort_without_partial_extraction() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("files.zip");
+        write_zip(
+            &archive,
+            &[
+                Entry::File("tool/a", vec![b'a'; 40]),
+                Entry::File("tool/sub/b", vec![b'b'; 40]),
+                Entry::File("tool/zeros", vec![0; 1 << 20]),
+            ],
+        );
+        let defaults = ExtractLimits::default();
+        assert_limit_error(
+            ArchiveKind::Zip,
+            &archive,
+            ExtractLimits {
+                max_entries: 2,
+                ..defaults.clone()
+            },
+        );
+        assert_limit_error(
+            ArchiveKind::Zip,
+            &archive,
+            ExtractLimits {
+                max_total_size: 100,
+                ..defaults.clone()
+            },
+        );
+        // коэффициент сжатия из заголовка zip
+        assert_limit_error(ArchiveKind::Zip, &archive, defaults);
+    }
+}
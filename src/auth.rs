@@ -0,0 +1,132 @@
+use crate::config_manager::Config;
+use actix_cors::Cors;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, header};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse};
+use anyhow::Context;
+use once_cell::sync::OnceCell;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// Файл с токеном API, лежит рядом с config.json
+const TOKEN_FILE: &str = "api_token";
+
+/// Токен, загруженный при старте через load_or_create_token()
+static TOKEN: OnceCell<String> = OnceCell::new();
+
+/// Загружает токен API из файла `api_token` или, при первом запуске, генерирует новый
+/// (32 случайных байта в hex) и сохраняет его с правами только для владельца.
+/// Возвращает ошибку, если файл нельзя прочитать/создать или он пустой.
+pub fn load_or_create_token() -> anyhow::Result<&'static str> {
+    let token = TOKEN.get_or_try_init(|| -> anyhow::Result<String> {
+        let path = Path::new(TOKEN_FILE);
+        if path.exists() {
+            let token = fs::read_to_string(path)
+                .context("Failed to read API token file")?
+                .trim()
+                .to_string();
+            anyhow::ensure!(!token.is_empty(), "API token file {} is empty", TOKEN_FILE);
+            return Ok(token);
+        }
+
+        let mut bytes = [0u8; 32];
+        getrandom::fill(&mut bytes)
+            .map_err(|e| anyhow::anyhow!("Failed to generate API token: {}", e))?;
+        let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options
+            .open(path)
+            .and_then(|mut f| f.write_all(token.as_bytes()))
+            .context("Failed to write API token file")?;
+
+        println!("Created new API token file: {}", TOKEN_FILE);
+        Ok(token)
+    })?;
+    Ok(token.as_str())
+}
+
+/// Сравнение строк за время, не зависящее от позиции первого различия.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Проверяет заголовок `Authorization: Bearer <token>`.
+fn has_valid_token(req: &ServiceRequest) -> bool {
+    let Some(expected) = TOKEN.get() else {
+        return false;
+    };
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| constant_time_eq(token.trim().as_bytes(), expected.as_bytes()))
+}
+
+/// Эндпоинты для чтения, которые тоже требуют токен: отчёт doctor раскрывает пути,
+/// версии инструментов и настройки
+const PROTECTED_READS: [&str; 1] = ["/doctor"];
+
+/// Middleware: для изменяющих запросов (POST, PUT, PATCH, DELETE) и PROTECTED_READS требует
+/// bearer-токен, иначе отвечает 401 с JSON-телом ошибки. Остальное чтение (GET, OPTIONS)
+/// пропускается без токена.
+pub async fn require_token<B: MessageBody>(
+    req: ServiceRequest,
+    next: Next<B>,
+) -> Result<ServiceResponse<EitherBody<B>>, Error> {
+    let mutating = matches!(
+        *req.method(),
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    );
+    let protected = mutating
+        || (*req.method() != Method::OPTIONS
+            && PROTECTED_READS.contains(&req.path().trim_end_matches('/')));
+    if protected && !has_valid_token(&req) {
+        let response = HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "unauthorized",
+            "message": "missing or invalid bearer token",
+            "status": 401,
+        }));
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
+
+/// Проверяет Origin по списку Config::allowed_origins.
+/// Элемент, заканчивающийся на '*', задаёт префикс (например "moz-extension://*").
+fn origin_allowed(origin: &str, allowed: &[String]) -> bool {
+    allowed
+        .iter()
+        .any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => origin.starts_with(prefix),
+            None => origin == pattern,
+        })
+}
+
+/// CORS только для origin из Config::allowed_origins.
+pub fn cors_middleware(config: &'static Config) -> Cors {
+    Cors::default()
+        .allowed_origin_fn(move |origin, _| {
+            origin
+                .to_str()
+                .is_ok_and(|origin| origin_allowed(origin, &config.allowed_origins))
+        })
+        .allowed_methods(vec!["GET", "POST", "DELETE", "PUT"])
+        .allowed_headers(vec![
+            header::AUTHORIZATION,
+            header::ACCEPT,
+            header::CONTENT_TYPE,
+        ])
+        .max_age(3600)
+}
@@ -0,0 +1,62 @@
+use crate::structures::structs_git::Asset;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Имена asset'ов с контрольными суммами, которые публикуют поддерживаемые проекты:
+/// yt-dlp — "SHA2-256SUMS", BtbN/FFmpeg-Builds — "checksums.sha256".
+const CHECKSUM_ASSETS: &[&str] = &["SHA2-256SUMS", "checksums.sha256", "sha256sums.txt"];
+
+/// Ищет среди asset'ов релиза файл с SHA-256 суммами для `asset_name`:
+/// общий список из CHECKSUM_ASSETS или отдельный "<asset_name>.sha256".
+pub fn find_checksum_asset<'a>(assets: &'a [Asset], asset_name: &str) -> Option<&'a Asset> {
+    let own = format!("{}.sha256", asset_name);
+    assets
+        .iter()
+        .find(|a| a.name.eq_ignore_ascii_case(&own))
+        .or_else(|| {
+            assets.iter().find(|a| {
+                CHECKSUM_ASSETS
+                    .iter()
+                    .any(|name| a.name.eq_ignore_ascii_case(name))
+            })
+        })
+}
+
+/// Разбирает файл в формате sha256sum: "<hex>  <имя>" или "<hex> *<имя>" на строку.
+/// Возвращает соответствие имя файла → hex-дайджест в нижнем регистре.
+pub fn parse_checksums(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter_map(|line| {
+            let (hash, name) = line.trim().split_once(char::is_whitespace)?;
+            let name = name.trim_start().trim_start_matches('*');
+            let valid = hash.len() == 64 && hash.bytes().all(|b| b.is_ascii_hexdigit());
+            valid.then(|| (name.to_string(), hash.to_ascii_lowercase()))
+        })
+        .collect()
+}
+
+/// Находит ожидаемый дайджест для `asset_name` в содержимом файла сумм.
+/// Отдельный файл "<asset>.sha256" может содержать только хэш без имени.
+pub fn expected_digest(checksums: &str, asset_name: &str) -> Option<String> {
+    let parsed = parse_checksums(checksums);
+    if let Some(hash) = parsed.get(asset_name) {
+        return Some(hash.clone());
+    }
+    let bare = checksums.trim();
+    (bare.len() == 64 && bare.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| bare.to_ascii_lowercase())
+}
+
+/// SHA-256 файла в hex (нижний регистр).
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
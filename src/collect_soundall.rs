@@ -1,10 +1,8 @@
 use crate::structures::vk_data::{Data, Demo};
 use rayon::prelude::*;
 use regex::Regex;
-use serde::{Deserialize, Serialize};
 use serde_json::to_string_pretty;
 use std::fs;
-use std::io::Write;
 use std::path::{Path, PathBuf};
 use urlencoding::encode;
 
@@ -14,8 +12,7 @@ fn try_load(path: &Path) -> Option<Data> {
         .and_then(|s| serde_json::from_str::<Data>(&s).ok())
 }
 
-pub fn collect_sb(directory: &str) -> i32 {
-    let dir = Path::new(directory);
+pub fn collect_sb(dir: &Path) -> i32 {
     if !dir.exists() || !dir.is_dir() {
         eprintln!("Указанная директория не существует.");
         return 1;
@@ -43,51 +40,52 @@ pub fn collect_sb(directory: &str) -> i32 {
                 return None;
             }
             let m = try_load(&file)?;
-            let artist = m.safeArtist.trim().to_string();
-            let title = m.safeTitle.trim().to_string();
-            let index = m.index.trim().to_string();
+            let artist = m.safe_artist.trim().to_string();
+            let title = m.safe_title.trim().to_string();
+            // запросы версии 1 не передают index, пустой index legacy-формата — признак неполной записи
+            let index = m.index.as_deref().map(str::trim);
             if artist.is_empty() && title.is_empty() {
                 return None;
             }
             let combined = format!("{} - {}", artist, title);
             let cleaned = re_nonword.replace_all(&combined, "").to_string();
             let cleaned = re_digits.replace_all(&cleaned, "").to_string();
-            if cleaned.is_empty() || index.is_empty() {
+            if cleaned.is_empty() || index == Some("") {
                 return None;
             }
             Some(Demo {
-                safeArtistTitle: combined.clone(),
-                Uri: format!("https://vk.ru/audio?q={}", encode(&combined)),
+                safe_artist_title: combined.clone(),
+                uri: format!("https://vk.ru/audio?q={}", encode(&combined)),
             })
         })
         .collect();
 
     results.sort_by(|a, b| {
         let artist_a = a
-            .safeArtistTitle
+            .safe_artist_title
             .split(" - ")
             .next()
             .unwrap_or("")
             .to_lowercase();
         let artist_b = b
-            .safeArtistTitle
+            .safe_artist_title
             .split(" - ")
             .next()
             .unwrap_or("")
             .to_lowercase();
 
         match artist_a.cmp(&artist_b) {
-            std::cmp::Ordering::Equal => a.safeArtistTitle.cmp(&b.safeArtistTitle),
+            std::cmp::Ordering::Equal => a.safe_artist_title.cmp(&b.safe_artist_title),
             other => other,
         }
     });
 
-    results.dedup_by(|a, b| a.safeArtistTitle == b.safeArtistTitle && a.Uri == b.Uri);
+    results.dedup_by(|a, b| a.safe_artist_title == b.safe_artist_title && a.uri == b.uri);
 
     let out_path = dir.join("soundall.json");
     match to_string_pretty(&results) {
         Ok(json) => {
-            if let Err(_) = fs::write(&out_path, json) {
+            if fs::write(&out_path, json).is_err() {
                 eprintln!("Error writing the file.");
                 return 2;
             }
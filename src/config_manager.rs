@@ -1,15 +1,184 @@
+use crate::loudness::LoudnessMode;
+use crate::tool_spec::ToolSpec;
 use anyhow::Context;
 use once_cell::sync::OnceCell;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::{env, fs};
 
+/// Configuration file, looked up in the working directory
+pub const CONFIG_FILE: &str = "config.json";
+
 /// Configuration structure for the application
 /// Serializes/deserializes to/from JSON format
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     /// Path where downloaded files will be saved
     pub download_path: String,
+    /// Maximum number of download jobs processed at the same time
+    #[serde(default = "default_max_concurrent_jobs")]
+    pub max_concurrent_jobs: usize,
+    /// How many finished jobs GET /jobs keeps; the oldest ones beyond this are forgotten
+    #[serde(default = "default_finished_jobs_limit")]
+    pub finished_jobs_limit: usize,
+    /// Seconds a finished job is kept before it is forgotten (at least 60)
+    #[serde(default = "default_finished_job_ttl_secs")]
+    pub finished_job_ttl_secs: u64,
+    /// Output format used when a request does not set options.audio_format:
+    /// "mp3", "opus", "m4a" or "flac" are tagged with cover art, e.g. "opus"
+    #[serde(default = "default_audio_format")]
+    pub audio_format: String,
+    /// Loudness stage used when a request does not set options.loudness:
+    /// "off", "replay_gain" (measure EBU R128 and write ReplayGain tags)
+    /// or "normalize" (two-pass loudnorm to loudness_target_lufs, re-encodes the audio)
+    #[serde(default)]
+    pub loudness: LoudnessMode,
+    /// Target integrated loudness for "normalize", in LUFS (-70 to -5)
+    #[serde(default = "default_loudness_target_lufs")]
+    pub loudness_target_lufs: f64,
+    /// Origins allowed to call the API from a browser (CORS)
+    /// A trailing '*' matches any suffix, e.g. "moz-extension://*"
+    #[serde(default = "default_allowed_origins")]
+    pub allowed_origins: Vec<String>,
+    /// Address the HTTP server binds to
+    #[serde(default = "default_host")]
+    pub host: String,
+    /// Preferred TCP port; the next free port is used if it is busy
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Whether to listen on TCP at all (false together with unix_socket = socket-only)
+    #[serde(default = "default_listen_tcp")]
+    pub listen_tcp: bool,
+    /// Optional Unix domain socket path to listen on (Unix only)
+    #[serde(default)]
+    pub unix_socket: Option<String>,
+    /// Release tags to pin tools to, e.g. {"yt-dlp": "2025.01.15"}
+    /// Tools without an entry follow the latest release
+    #[serde(default)]
+    pub pinned_tools: BTreeMap<String, String>,
+    /// Additional tools to install into bin_ next to yt-dlp and ffmpeg, e.g.
+    /// {"name": "aria2c", "source": {"type": "github", "repo": "aria2/aria2"},
+    ///  "assets": [{"pattern": "win-64bit.*\\.zip$", "os": "windows", "arch": "x86_64"}],
+    ///  "extract": ["*/aria2c.exe"], "allow_unverified": true}
+    /// An entry named "yt-dlp", "ffmpeg" or "curl" (Windows) replaces the built-in one
+    #[serde(default)]
+    pub extra_tools: Vec<ToolSpec>,
+    /// GitHub API base URL (e.g. a mirror or a local mock for testing)
+    #[serde(default = "default_github_api_url")]
+    pub github_api_url: String,
+    /// Longest wait, in seconds, for the GitHub API rate limit to reset before giving up
+    #[serde(default = "default_github_rate_limit_wait_secs")]
+    pub github_rate_limit_wait_secs: u64,
+    /// Offline tool bundle (a directory or .zip/.tar.gz/.tar.xz made by `export-tools`);
+    /// missing tools are installed from it before trying GitHub
+    #[serde(default)]
+    pub tool_bundle: Option<String>,
+    /// Proxy for all HTTP traffic and yt-dlp: "http://host:port", "socks5://host:port"
+    /// or "socks5h://host:port" (DNS through the proxy)
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// Seconds to wait for a TCP/TLS connection
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Seconds to wait for the next chunk of a response (also yt-dlp's --socket-timeout)
+    #[serde(default = "default_read_timeout_secs")]
+    pub read_timeout_secs: u64,
+    /// User-Agent for tool and cover downloads
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+    /// Extra root certificates (PEM files) to trust, e.g. a corporate TLS proxy CA
+    #[serde(default)]
+    pub ca_certificates: Vec<String>,
+    /// Referer sent with cover image requests
+    #[serde(default = "default_cover_referer")]
+    pub cover_referer: Option<String>,
+    /// Largest cover side in pixels after processing; 0 keeps the original size
+    #[serde(default = "default_cover_max_size")]
+    pub cover_max_size: u32,
+    /// Center-crop covers to a square before resizing
+    #[serde(default)]
+    pub cover_square: bool,
+    /// JPEG quality (1-100) for embedded covers
+    #[serde(default = "default_cover_jpeg_quality")]
+    pub cover_jpeg_quality: u8,
+    /// Netscape cookies file passed to yt-dlp as --cookies
+    #[serde(default)]
+    pub cookies_file: Option<String>,
+    /// Browser to take cookies from, passed to yt-dlp as --cookies-from-browser (e.g. "firefox")
+    #[serde(default)]
+    pub cookies_from_browser: Option<String>,
+}
+
+fn default_max_concurrent_jobs() -> usize {
+    2
+}
+
+fn default_finished_jobs_limit() -> usize {
+    200
+}
+
+fn default_finished_job_ttl_secs() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_audio_format() -> String {
+    "mp3".to_string()
+}
+
+fn default_loudness_target_lufs() -> f64 {
+    -14.0
+}
+
+fn default_host() -> String {
+    "127.0.0.1".to_string()
+}
+
+fn default_port() -> u16 {
+    1488
+}
+
+fn default_listen_tcp() -> bool {
+    true
+}
+
+fn default_github_api_url() -> String {
+    "https://api.github.com".to_string()
+}
+
+fn default_github_rate_limit_wait_secs() -> u64 {
+    60
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    15
+}
+
+fn default_read_timeout_secs() -> u64 {
+    60
+}
+
+fn default_user_agent() -> String {
+    "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/138.0.0.0 Safari/537.36".to_string()
+}
+
+fn default_cover_referer() -> Option<String> {
+    Some("https://vk.com/".to_string())
+}
+
+fn default_cover_max_size() -> u32 {
+    1000
+}
+
+fn default_cover_jpeg_quality() -> u8 {
+    90
+}
+
+fn default_allowed_origins() -> Vec<String> {
+    vec![
+        "chrome-extension://*".to_string(),
+        "moz-extension://*".to_string(),
+    ]
 }
 
 /// Global static configuration instance
@@ -36,6 +205,33 @@ impl Config {
 
         Ok(Config {
             download_path: default_path,
+            max_concurrent_jobs: default_max_concurrent_jobs(),
+            finished_jobs_limit: default_finished_jobs_limit(),
+            finished_job_ttl_secs: default_finished_job_ttl_secs(),
+            audio_format: default_audio_format(),
+            loudness: LoudnessMode::default(),
+            loudness_target_lufs: default_loudness_target_lufs(),
+            allowed_origins: default_allowed_origins(),
+            host: default_host(),
+            port: default_port(),
+            listen_tcp: default_listen_tcp(),
+            unix_socket: None,
+            pinned_tools: BTreeMap::new(),
+            extra_tools: Vec::new(),
+            github_api_url: default_github_api_url(),
+            github_rate_limit_wait_secs: default_github_rate_limit_wait_secs(),
+            tool_bundle: None,
+            proxy: None,
+            connect_timeout_secs: default_connect_timeout_secs(),
+            read_timeout_secs: default_read_timeout_secs(),
+            user_agent: default_user_agent(),
+            ca_certificates: Vec::new(),
+            cover_referer: default_cover_referer(),
+            cover_max_size: default_cover_max_size(),
+            cover_square: false,
+            cover_jpeg_quality: default_cover_jpeg_quality(),
+            cookies_file: None,
+            cookies_from_browser: None,
         })
     }
 
@@ -57,7 +253,7 @@ impl Config {
     /// # Returns
     /// Result<Config, anyhow::Error> - Loaded or newly created configuration
     fn load_or_create_internal() -> Result<Self, anyhow::Error> {
-        let config_path = "config.json";
+        let config_path = CONFIG_FILE;
 
         // Check if config file exists
         if !PathBuf::from(config_path).exists() {
@@ -73,15 +269,21 @@ impl Config {
             println!("Created new config file: {}", config_path);
             Ok(config)
         } else {
-            // Load existing config file
-            let data = fs::read_to_string(config_path).context("Failed to read config file")?;
+            Config::read_file()
+        }
+    }
 
-            // Deserialize from JSON
-            let config: Config =
-                serde_json::from_str(&data).context("Failed to parse config file")?;
+    /// Reads and parses the existing config file without touching the global instance
+    /// # Returns
+    /// Result<Config, anyhow::Error> - Parsed configuration
+    pub fn read_file() -> Result<Self, anyhow::Error> {
+        // Load existing config file
+        let data = fs::read_to_string(CONFIG_FILE).context("Failed to read config file")?;
 
-            Ok(config)
-        }
+        // Deserialize from JSON
+        let config: Config = serde_json::from_str(&data).context("Failed to parse config file")?;
+
+        Ok(config)
     }
 
     /// Gets the global configuration instance with lazy initialization
@@ -89,7 +291,7 @@ impl Config {
     /// # Returns
     /// Result<&'static Config, anyhow::Error> - Reference to global config
     pub fn get() -> Result<&'static Self, anyhow::Error> {
-        CONFIG.get_or_try_init(|| Config::load_or_create_internal())
+        CONFIG.get_or_try_init(Config::load_or_create_internal)
     }
 
     /// Gets the global configuration instance without Result wrapper
@@ -0,0 +1,191 @@
+use crate::config_manager::Config;
+use anyhow::{Context, bail};
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+
+/// Форматы обложек, которые принимаются от сервера
+const ACCEPTED_FORMATS: &[ImageFormat] = &[
+    ImageFormat::Jpeg,
+    ImageFormat::Png,
+    ImageFormat::WebP,
+    ImageFormat::Gif,
+    ImageFormat::Bmp,
+];
+
+/// Как готовить обложку к встраиванию.
+#[derive(Debug, Clone, Copy)]
+pub struct CoverOptions {
+    /// Наибольшая сторона в пикселях; 0 — не уменьшать
+    pub max_size: u32,
+    /// Обрезать по центру до квадрата
+    pub square: bool,
+    /// Качество JPEG, 1-100
+    pub jpeg_quality: u8,
+}
+
+impl CoverOptions {
+    pub fn from_config(config: &Config) -> CoverOptions {
+        CoverOptions {
+            max_size: config.cover_max_size,
+            square: config.cover_square,
+            jpeg_quality: config.cover_jpeg_quality.clamp(1, 100),
+        }
+    }
+}
+
+/// Определяет формат по содержимому, а не по имени файла или Content-Type.
+/// Не картинка (например, HTML-страница ошибки с кодом 200) — ошибка.
+fn sniff_format(bytes: &[u8]) -> anyhow::Result<ImageFormat> {
+    match image::guess_format(bytes) {
+        Ok(format) if ACCEPTED_FORMATS.contains(&format) => Ok(format),
+        Ok(format) => bail!("unsupported cover image format {:?}", format),
+        Err(_) => {
+            let head = String::from_utf8_lossy(&bytes[..bytes.len().min(512)]).to_ascii_lowercase();
+            if head.trim_start().starts_with('<') || head.contains("<html") {
+                bail!("server returned an HTML page instead of a cover image");
+            }
+            bail!("response is not an image ({} bytes)", bytes.len())
+        }
+    }
+}
+
+/// RGB без прозрачности: прозрачные области кладутся на белый фон (в JPEG нет альфа-канала).
+fn flatten_on_white(image: DynamicImage) -> RgbImage {
+    if !image.color().has_alpha() {
+        return image.into_rgb8();
+    }
+    let rgba = image.into_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend =
+            |c: u8| ((u16::from(c) * u16::from(a) + 255 * (255 - u16::from(a))) / 255) as u8;
+        Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+/// Готовит обложку к встраиванию: проверяет, что это картинка (JPEG/PNG/WebP/GIF/BMP),
+/// при `square` обрезает по центру до квадрата, уменьшает до `max_size` по большей стороне
+/// и перекодирует в baseline JPEG — такие обложки понимают и старые плееры, и магнитолы.
+pub fn process_cover(bytes: &[u8], options: CoverOptions) -> anyhow::Result<Vec<u8>> {
+    let format = sniff_format(bytes)?;
+    let mut image = image::load_from_memory_with_format(bytes, format)
+        .with_context(|| format!("cannot decode {:?} cover", format))?;
+
+    if options.square && image.width() != image.height() {
+        let side = image.width().min(image.height());
+        let x = (image.width() - side) / 2;
+        let y = (image.height() - side) / 2;
+        image = image.crop_imm(x, y, side, side);
+    }
+    if options.max_size > 0 && image.width().max(image.height()) > options.max_size {
+        image = image.resize(options.max_size, options.max_size, FilterType::Lanczos3);
+    }
+
+    let rgb = flatten_on_white(image);
+    let mut jpeg = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, options.jpeg_quality)
+        .encode_image(&rgb)
+        .context("cannot encode cover as JPEG")?;
+    Ok(jpeg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgba, RgbaImage};
+    use std::io::Cursor;
+
+    fn options(max_size: u32, square: bool) -> CoverOptions {
+        CoverOptions {
+            max_size,
+            square,
+            jpeg_quality: 95,
+        }
+    }
+
+    fn png(image: RgbaImage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(image)
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    fn decode(jpeg: &[u8]) -> DynamicImage {
+        image::load_from_memory_with_format(jpeg, ImageFormat::Jpeg).unwrap()
+    }
+
+    /// Канал близок к ожидаемому с поправкой на потери JPEG.
+    fn assert_near(actual: u8, expected: u8) {
+        assert!(
+            actual.abs_diff(expected) <= 8,
+            "{} is not close to {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn html_error_page_is_rejected() {
+        let page = b"<!DOCTYPE html><html><body>502 Bad Gateway</body></html>";
+        let error = process_cover(page, options(0, false)).unwrap_err();
+        assert!(error.to_string().contains("HTML page"), "{}", error);
+    }
+
+    #[test]
+    fn transparency_is_flattened_onto_white() {
+        let image = RgbaImage::from_fn(16, 16, |x, _| {
+            if x < 8 {
+                Rgba([0, 0, 0, 0])
+            } else {
+                Rgba([0, 0, 0, 128])
+            }
+        });
+        let cover = decode(&process_cover(&png(image), options(0, false)).unwrap());
+        let transparent = cover.get_pixel(2, 8).0;
+        let half = cover.get_pixel(13, 8).0;
+        for channel in 0..3 {
+            assert_near(transparent[channel], 255);
+            assert_near(half[channel], 127);
+        }
+    }
+
+    #[test]
+    fn square_crops_the_center() {
+        // 48x16: красный квадрат посередине, по бокам синие поля
+        let image = RgbaImage::from_fn(48, 16, |x, _| {
+            if (16..32).contains(&x) {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        });
+        let cover = decode(&process_cover(&png(image), options(0, true)).unwrap());
+        assert_eq!(cover.dimensions(), (16, 16));
+        for (x, y) in [(1, 1), (14, 14), (8, 8)] {
+            let [r, _, b, _] = cover.get_pixel(x, y).0;
+            assert!(r > 200 && b < 60, "pixel ({}, {}) is not red", x, y);
+        }
+    }
+
+    #[test]
+    fn max_size_limits_the_longer_side_and_zero_keeps_the_size() {
+        let bytes = png(RgbaImage::from_pixel(60, 20, Rgba([10, 20, 30, 255])));
+        let kept = decode(&process_cover(&bytes, options(0, false)).unwrap());
+        assert_eq!(kept.dimensions(), (60, 20));
+        let resized = decode(&process_cover(&bytes, options(30, false)).unwrap());
+        assert_eq!(resized.dimensions(), (30, 10));
+    }
+
+    #[test]
+    fn output_is_baseline_jpeg() {
+        let bytes = png(RgbaImage::from_pixel(32, 32, Rgba([200, 100, 50, 255])));
+        let jpeg = process_cover(&bytes, options(0, false)).unwrap();
+        assert_eq!(&jpeg[..2], [0xFF, 0xD8]);
+        let has_marker = |marker: u8| jpeg.windows(2).any(|w| w == [0xFF, marker]);
+        // SOF0 — baseline, SOF2 — progressive
+        assert!(has_marker(0xC0));
+        assert!(!has_marker(0xC2));
+    }
+}
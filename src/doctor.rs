@@ -0,0 +1,440 @@
+use crate::config_manager::{CONFIG_FILE, Config};
+use crate::listener::{DISCOVERY_FILE, Endpoint};
+use crate::tool_locator::{BIN_DIR, resolve};
+use crate::tool_spec::{FFMPEG, REQUIRED_TOOLS, tool_specs};
+use crate::ytdlp_options::audio_encoder;
+use serde::Serialize;
+use std::io;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Сколько ждать `<tool> --version` и списка кодеков ffmpeg
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Свободное место в каталоге загрузок: меньше — предупреждение / ошибка
+const LOW_DISK_SPACE: u64 = 1 << 30;
+const MIN_DISK_SPACE: u64 = 100 << 20;
+
+/// Индекс треков в каталоге загрузок (см. collect_sb)
+const SOUNDALL_FILE: &str = "soundall.json";
+
+/// Результат одной проверки; порядок вариантов — по серьёзности.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+/// Отчёт doctor: общий статус — худший из статусов проверок.
+#[derive(Debug, Serialize)]
+pub struct DoctorReport {
+    pub status: CheckStatus,
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    fn push(&mut self, name: impl Into<String>, status: CheckStatus, message: impl Into<String>) {
+        self.status = self.status.max(status);
+        self.checks.push(Check {
+            name: name.into(),
+            status,
+            message: message.into(),
+        });
+    }
+}
+
+/// Запускает `exe args` с таймаутом и возвращает stdout; ошибка — если процесс не запустился,
+/// завис или завершился с ненулевым кодом.
+async fn run_tool(exe: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new(exe)
+        .args(args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(COMMAND_TIMEOUT, output).await {
+        Err(_) => Err(format!("timed out after {}s", COMMAND_TIMEOUT.as_secs())),
+        Ok(Err(e)) => Err(e.to_string()),
+        Ok(Ok(output)) if !output.status.success() => Err(format!(
+            "exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Ok(Ok(output)) => Ok(String::from_utf8_lossy(&output.stdout).into_owned()),
+    }
+}
+
+/// Размер в удобочитаемом виде: "1.5 GiB".
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// config.json читается заново: ошибка в файле видна, даже если сервер запущен со старой версией.
+fn check_config(report: &mut DoctorReport) {
+    match Config::read_file() {
+        Ok(_) => report.push(
+            "config",
+            CheckStatus::Pass,
+            format!("{} parsed", CONFIG_FILE),
+        ),
+        Err(e) => report.push("config", CheckStatus::Fail, format!("{:#}", e)),
+    }
+}
+
+/// Каталог загрузок существует, доступен на запись и на диске есть место.
+fn check_download_path(report: &mut DoctorReport, config: &Config) -> Option<PathBuf> {
+    let path = PathBuf::from(shellexpand::tilde(&config.download_path).into_owned());
+    if !path.is_dir() {
+        report.push(
+            "download_path",
+            CheckStatus::Fail,
+            format!("{} does not exist or is not a folder", path.display()),
+        );
+        return None;
+    }
+    let probe = path.join(".ytdlp-vk-doctor");
+    match std::fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            report.push(
+                "download_path",
+                CheckStatus::Pass,
+                format!("{} is writable", path.display()),
+            );
+        }
+        Err(e) => report.push(
+            "download_path",
+            CheckStatus::Fail,
+            format!("{} is not writable: {}", path.display(), e),
+        ),
+    }
+
+    match fs4::available_space(&path) {
+        Ok(free) => {
+            let status = if free < MIN_DISK_SPACE {
+                CheckStatus::Fail
+            } else if free < LOW_DISK_SPACE {
+                CheckStatus::Warn
+            } else {
+                CheckStatus::Pass
+            };
+            report.push("disk_space", status, format!("{} free", human_size(free)));
+        }
+        Err(e) => report.push(
+            "disk_space",
+            CheckStatus::Warn,
+            format!("cannot determine free space: {}", e),
+        ),
+    }
+    Some(path)
+}
+
+/// Выполняет блокирующие проверки (файлы, bind) в пуле blocking-потоков tokio,
+/// а не на рабочем потоке actix.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    tokio::task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic()))
+}
+
+/// Инструменты находятся и запускаются; у ffmpeg есть кодер для формата `audio_format`.
+async fn check_tools(report: &mut DoctorReport, audio_format: &str) {
+    let resolved = blocking(|| {
+        let mut names: Vec<String> = REQUIRED_TOOLS.iter().map(|s| s.to_string()).collect();
+        for spec in tool_specs() {
+            if !names.contains(&spec.name) {
+                names.push(spec.name);
+            }
+        }
+        names
+            .into_iter()
+            .map(|name| {
+                let path = resolve(&name);
+                (name, path)
+            })
+            .collect::<Vec<_>>()
+    })
+    .await;
+
+    for (name, path) in resolved {
+        let check = format!("tool:{}", name);
+        let required = REQUIRED_TOOLS.contains(&name.as_str());
+        let Some(path) = path else {
+            let status = if required {
+                CheckStatus::Fail
+            } else {
+                CheckStatus::Warn
+            };
+            report.push(check, status, format!("not found in {} or PATH", BIN_DIR));
+            continue;
+        };
+        let version_flag = if name == FFMPEG {
+            "-version"
+        } else {
+            "--version"
+        };
+        match run_tool(&path, &[version_flag]).await {
+            Ok(out) => report.push(
+                check,
+                CheckStatus::Pass,
+                format!(
+                    "{} ({})",
+                    out.lines().next().unwrap_or("").trim(),
+                    path.display()
+                ),
+            ),
+            Err(e) => {
+                // у дополнительных инструментов может не быть --version
+                let status = if required {
+                    CheckStatus::Fail
+                } else {
+                    CheckStatus::Warn
+                };
+                report.push(check, status, format!("{} failed: {}", path.display(), e));
+            }
+        }
+
+        if name == FFMPEG {
+            check_encoders(report, &path, audio_format).await;
+        }
+    }
+}
+
+/// Есть ли `codec` в выводе `ffmpeg -encoders`: имя кодера — второе поле строки после флагов.
+fn has_encoder(encoders: &str, codec: &str) -> bool {
+    encoders
+        .lines()
+        .any(|line| line.split_whitespace().nth(1) == Some(codec))
+}
+
+async fn check_encoders(report: &mut DoctorReport, ffmpeg: &Path, audio_format: &str) {
+    let Some(codec) = audio_encoder(audio_format) else {
+        return;
+    };
+    let encoders = match run_tool(ffmpeg, &["-hide_banner", "-encoders"]).await {
+        Ok(out) => out,
+        Err(e) => {
+            report.push(
+                "codecs",
+                CheckStatus::Fail,
+                format!("cannot list ffmpeg encoders: {}", e),
+            );
+            return;
+        }
+    };
+    let (status, message) = if has_encoder(&encoders, codec) {
+        (
+            CheckStatus::Pass,
+            format!("available ({} output)", audio_format),
+        )
+    } else {
+        (
+            CheckStatus::Fail,
+            format!("missing from ffmpeg, needed for {} output", audio_format),
+        )
+    };
+    report.push(format!("codec:{}", codec), status, message);
+}
+
+/// Порт сервера: слушает ли его этот процесс, свободен ли он или занят кем-то ещё.
+fn check_port(report: &mut DoctorReport, config: &Config) {
+    if !config.listen_tcp {
+        report.push("port", CheckStatus::Pass, "TCP listener disabled");
+        return;
+    }
+    let running: Option<Endpoint> = std::fs::read_to_string(DISCOVERY_FILE)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok());
+    if let Some(endpoint) = &running
+        && endpoint.pid == std::process::id()
+        && let Some(url) = &endpoint.url
+    {
+        report.push("port", CheckStatus::Pass, format!("listening on {}", url));
+        return;
+    }
+
+    match TcpListener::bind((config.host.as_str(), config.port)) {
+        Ok(_) => report.push(
+            "port",
+            CheckStatus::Pass,
+            format!("{}:{} is free", config.host, config.port),
+        ),
+        Err(e) if e.kind() == io::ErrorKind::AddrInUse => {
+            let owner = running
+                .map(|endpoint| format!(" (another instance, pid {}?)", endpoint.pid))
+                .unwrap_or_default();
+            report.push(
+                "port",
+                CheckStatus::Warn,
+                format!(
+                    "{}:{} is in use{}; the server will take the next free port",
+                    config.host, config.port, owner
+                ),
+            );
+        }
+        Err(e) => report.push(
+            "port",
+            CheckStatus::Fail,
+            format!("cannot bind {}:{}: {}", config.host, config.port, e),
+        ),
+    }
+}
+
+/// soundall.json в каталоге загрузок читается и разбирается.
+fn check_soundall(report: &mut DoctorReport, download_path: &Path) {
+    let path = download_path.join(SOUNDALL_FILE);
+    if !path.exists() {
+        report.push(
+            "soundall",
+            CheckStatus::Warn,
+            format!(
+                "{} not found; it is created after the first download",
+                path.display()
+            ),
+        );
+        return;
+    }
+    let parsed = std::fs::read_to_string(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|json| {
+            serde_json::from_str::<Vec<serde_json::Value>>(&json).map_err(|e| e.to_string())
+        });
+    match parsed {
+        Ok(entries) => report.push(
+            "soundall",
+            CheckStatus::Pass,
+            format!("{} ({} entries)", path.display(), entries.len()),
+        ),
+        Err(e) => report.push(
+            "soundall",
+            CheckStatus::Fail,
+            format!("{} is unreadable: {}", path.display(), e),
+        ),
+    }
+}
+
+/// Проверяет окружение целиком: конфиг, каталог загрузок и место на диске, инструменты и их
+/// запуск, кодер ffmpeg для Config::audio_format, порт сервера и индекс soundall.json.
+/// Если конфиг не загружен, проверки, которым он нужен, пропускаются (проверка config уже Fail).
+/// Файловые проверки и bind выполняются вне асинхронных потоков (см. blocking).
+pub async fn run_doctor_async() -> DoctorReport {
+    let (mut report, config, download_path) = blocking(|| {
+        let mut report = DoctorReport {
+            status: CheckStatus::Pass,
+            checks: Vec::new(),
+        };
+        check_config(&mut report);
+        let config = Config::get().ok();
+        let download_path = config.and_then(|config| check_download_path(&mut report, config));
+        (report, config, download_path)
+    })
+    .await;
+    let audio_format = config.map_or("mp3", |config| config.audio_format.as_str());
+    check_tools(&mut report, audio_format).await;
+    blocking(move || {
+        if let Some(config) = config {
+            check_port(&mut report, config);
+        }
+        if let Some(path) = download_path {
+            check_soundall(&mut report, &path);
+        }
+        report
+    })
+    .await
+}
+
+/// Печатает отчёт построчно: "[warn] disk_space: 800.0 MiB free".
+pub fn print_report(report: &DoctorReport) {
+    for check in &report.checks {
+        let status = match check.status {
+            CheckStatus::Pass => "pass",
+            CheckStatus::Warn => "warn",
+            CheckStatus::Fail => "FAIL",
+        };
+        println!("[{}] {}: {}", status, check.name, check.message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_report() -> DoctorReport {
+        DoctorReport {
+            status: CheckStatus::Pass,
+            checks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn human_size_picks_largest_unit() {
+        assert_eq!(human_size(0), "0.0 B");
+        assert_eq!(human_size(1023), "1023.0 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(5 * 1024 * 1024), "5.0 MiB");
+        assert_eq!(human_size(3 << 40), "3072.0 GiB");
+    }
+
+    #[test]
+    fn report_status_is_worst_check() {
+        let mut report = empty_report();
+        report.push("a", CheckStatus::Pass, "");
+        assert_eq!(report.status, CheckStatus::Pass);
+        report.push("b", CheckStatus::Fail, "");
+        report.push("c", CheckStatus::Warn, "");
+        assert_eq!(report.status, CheckStatus::Fail);
+        assert_eq!(report.checks.len(), 3);
+        assert_eq!(report.checks[2].status, CheckStatus::Warn);
+    }
+
+    #[test]
+    fn has_encoder_matches_encoder_name_column() {
+        let encoders = "Encoders:
+ V..... = Video
+ ------
+ A....D aac                  AAC (Advanced Audio Coding)
+ A..... libmp3lame           libmp3lame MP3 (MPEG audio layer 3) (codec mp3)
+ A..... libopus              libopus Opus (codec opus)
+";
+        assert!(has_encoder(encoders, "libmp3lame"));
+        assert!(has_encoder(encoders, "aac"));
+        assert!(!has_encoder(encoders, "mp3"));
+        assert!(!has_encoder(encoders, "libvorbis"));
+        assert!(!has_encoder("", "aac"));
+    }
+
+    #[test]
+    fn check_soundall_reports_missing_malformed_and_valid_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut report = empty_report();
+        check_soundall(&mut report, dir.path());
+        assert_eq!(report.checks[0].status, CheckStatus::Warn);
+
+        std::fs::write(dir.path().join(SOUNDALL_FILE), "{not json").unwrap();
+        check_soundall(&mut report, dir.path());
+        assert_eq!(report.checks[1].status, CheckStatus::Fail);
+
+        std::fs::write(dir.path().join(SOUNDALL_FILE), r#"[{"id": 1}, {"id": 2}]"#).unwrap();
+        check_soundall(&mut report, dir.path());
+        assert_eq!(report.checks[2].status, CheckStatus::Pass);
+        assert!(report.checks[2].message.ends_with("(2 entries)"));
+    }
+}
@@ -1,400 +1,509 @@
-use crate::path_ext::{extract_output_path, join_path, remove_and_rename};
-use crate::process_manager::{embed_title_and_artwork_with_ffmpeg, spawn_and_log_io};
-use crate::structures::structs_git::{Asset, Release};
-use crate::zip_extractor::extract_prefix_from_zip;
-use anyhow::Context;
-use futures_util::StreamExt;
-use reqwest::header::{ACCEPT, AUTHORIZATION, REFERER, USER_AGENT};
-use std::error::Error;
+use crate::collect_soundall::collect_sb;
+use crate::config_manager::Config;
+use crate::cover_art::{CoverOptions, process_cover};
+use crate::http_client::http_client;
+use crate::job_queue::{JobHandle, JobState};
+use crate::loudness::{LoudnessMode, process_loudness};
+use crate::path_ext::confine_to_base;
+use crate::pipeline_error::PipelineError;
+use crate::process_manager::spawn_and_log_io;
+use crate::progress::{self, parse_ytdlp_progress};
+use crate::structures::download_request::DownloadRequest;
+use crate::tag_writer::{TagFormat, write_tags};
+use crate::tool_locator::{BIN_DIR, resolve};
+use crate::tool_spec::{FFMPEG, YTDLP};
+use crate::ytdlp_options::{audio_encoder, build_ytdlp_args, network_args};
+use reqwest::StatusCode;
+use reqwest::header::{CONTENT_RANGE, HeaderMap, HeaderValue, RANGE, REFERER};
 use std::io;
-use std::io::Cursor;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::fs;
-use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
-use zip::ZipArchive;
-use crate::collect_soundall::collect_sb;
 
-pub fn check_bin_contains_ffmpeg_and_ytdlp(dir: &Path) -> bool {
-    if !dir.exists() || !dir.is_dir() {
-        return false;
-    }
+/// Сколько раз пытаться скачать файл и пауза перед первой повторной попыткой (дальше удваивается)
+const DOWNLOAD_ATTEMPTS: u32 = 5;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
 
-    let ffmpeg = dir.join("ffmpeg.exe");
-    let ytdlp = dir.join("yt-dlp.exe");
+/// Наибольший размер скачиваемой обложки: больше — ошибка, а не заполненный диск
+const COVER_MAX_BYTES: u64 = 20 << 20;
 
-    ffmpeg.exists() && ffmpeg.is_file() && ytdlp.exists() && ytdlp.is_file()
+/// Ошибка попытки скачивания: можно ли повторить запрос.
+enum AttemptError {
+    Retry(anyhow::Error),
+    Fatal(anyhow::Error),
 }
 
-/// Асинхронно проверяет наличие локального файла "curl.exe" и при его отсутствии:
-/// - скачивает ZIP с https://curl.se/windows/latest.cgi?p=win64-mingw.zip;
-/// - распаковывает из архива файл "curl-8.17.0_5-win64-mingw/bin/curl.exe";
-/// - сохраняет его как "curl.exe".
-/// Возвращает ошибку при неудачном HTTP‑ответе, отсутствии целевого файла в архиве или при ошибках ввода/вывода.
-pub async fn fetch_curl_exe() -> anyhow::Result<(), Box<dyn std::error::Error>> {
-    let out_path = Path::new("curl.exe");
-    if out_path.exists() {
-        println!("curl.exe already exists, skipping download.");
-        return Ok(());
-    }
-
-    let url = "https://curl.se/windows/latest.cgi?p=win64-mingw.zip";
-    println!("Downloading {}", url);
-
-    let resp = reqwest::Client::new().get(url).send().await?;
-    if !resp.status().is_success() {
-        return Err(format!("Download failed: HTTP {}", resp.status()).into());
-    }
+/// Путь недокачанного файла: "<dest>.part".
+fn part_path(dest: &Path) -> PathBuf {
+    let mut name = dest.as_os_str().to_owned();
+    name.push(".part");
+    PathBuf::from(name)
+}
 
-    let bytes = resp.bytes().await?; // bytes::Bytes
-    let reader = Cursor::new(bytes);
-
-    let mut zip = ZipArchive::new(reader)?;
-
-    let target = "curl-8.17.0_5-win64-mingw/bin/curl.exe";
-    for i in 0..zip.len() {
-        let mut entry = zip.by_index(i)?;
-        let name = entry.name().replace('\\', "/");
-        if name == target {
-            let mut content: Vec<u8> = Vec::new();
-            std::io::Read::read_to_end(&mut entry, &mut content)?;
-            fs::write(out_path, &content).await?;
-            println!("Extracted curl.exe");
-            return Ok(());
-        }
+/// Полный размер файла из ответа: из Content-Range ("bytes 100-199/200" для 206,
+/// "bytes */200" для 416) или offset + Content-Length для 200.
+fn total_size(resp: &reqwest::Response, offset: u64) -> Option<u64> {
+    match resp.status() {
+        StatusCode::PARTIAL_CONTENT | StatusCode::RANGE_NOT_SATISFIABLE => resp
+            .headers()
+            .get(CONTENT_RANGE)?
+            .to_str()
+            .ok()?
+            .rsplit('/')
+            .next()?
+            .parse()
+            .ok(),
+        _ => resp.content_length().map(|len| offset + len),
     }
-
-    Err(format!("{} not found in archive", target).into())
 }
 
-/// Асинхронно загружает релиз yt-dlp с GitHub для указанного app_name:
-/// - если файл с именем app_name уже существует — ничего не делает;
-/// - запрашивает список релизов через GitHub API (поддерживается GITHUB_TOKEN для авторизации);
-/// - выбирает первый не‑draft релиз, фильтрует assets по совпадению имени с app_name и сортирует по размеру;
-/// - скачивает выбранный asset потоково и сохраняет под именем "bin_\\<app_name>".
-/// Возвращает сетевые и файловые ошибки при неудаче.
-pub async fn fetch_ytdlp_release_async(
-    app_name: &str,
-    github_api: &str,
-) -> anyhow::Result<(), Box<dyn Error + Send + Sync>> {
-    if fs::metadata(app_name).await.is_ok() {
-        return Ok(());
+/// Одна попытка: дозапрашивает файл с позиции `offset` (Range) и дописывает его в `part`.
+/// Возвращает полный размер файла, если сервер его сообщил.
+/// `expected_size` нужен, чтобы понять, докачан ли уже `part`, когда сервер отвечает 416.
+/// Файл больше `max_size` не докачивается: `part` удаляется, ошибка не повторяется.
+async fn download_attempt(
+    client: &reqwest::Client,
+    url: &str,
+    headers: &HeaderMap,
+    part: &Path,
+    offset: u64,
+    expected_size: Option<u64>,
+    max_size: Option<u64>,
+) -> Result<Option<u64>, AttemptError> {
+    use futures_util::StreamExt;
+    let mut req = client.get(url).headers(headers.clone());
+    if offset > 0 {
+        req = req.header(RANGE, format!("bytes={}-", offset));
     }
-
-    println!("downloading ytdlp for {}", app_name);
-    println!("{}", github_api);
-
-    let client = reqwest::Client::new();
-    let mut req = client
-        .get(github_api)
-        .header(USER_AGENT, "gh-download-rust/0.1")
-        .header(ACCEPT, "application/vnd.github.v3+json");
-
-    if let Ok(token) = std::env::var("GITHUB_TOKEN") {
-        req = req.header(AUTHORIZATION, format!("token {}", token));
+    let resp = req
+        .send()
+        .await
+        .map_err(|e| AttemptError::Retry(e.into()))?;
+    let status = resp.status();
+    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        // .part докачан, только если его размер совпадает с известным полным размером;
+        // иначе это устаревший или испорченный файл — качаем с начала
+        let total = expected_size.or_else(|| total_size(&resp, offset));
+        if total == Some(offset) {
+            return Ok(total);
+        }
+        let _ = fs::remove_file(part).await;
+        return Err(AttemptError::Retry(anyhow::anyhow!(
+            "range not satisfiable for {} bytes already downloaded",
+            offset
+        )));
+    }
+    if !status.is_success() {
+        let error = anyhow::anyhow!("HTTP error: {}", status);
+        return Err(
+            if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+                AttemptError::Retry(error)
+            } else {
+                AttemptError::Fatal(error)
+            },
+        );
     }
 
-    let releases: Vec<Release> = req.send().await?.error_for_status()?.json().await?;
-    let release = releases
-        .into_iter()
-        .find(|r| !r.draft)
-        .ok_or("No release found")?;
-
-    let mut assets: Vec<Asset> = release
-        .assets
-        .into_iter()
-        .filter(|a| a.name.to_lowercase().ends_with(&app_name.to_lowercase()))
-        .collect();
-    assets.sort_by_key(|a| std::cmp::Reverse(a.size.unwrap_or(0)));
-    let asset = match assets.into_iter().next() {
-        Some(a) => a,
-        None => return Ok(()),
+    // 200 на запрос с Range: сервер не умеет докачку — начинаем файл заново
+    let resumed = status == StatusCode::PARTIAL_CONTENT;
+    let total = total_size(&resp, if resumed { offset } else { 0 });
+    let too_large = |size: u64| max_size.is_some_and(|max| size > max);
+    let too_large_error = || {
+        AttemptError::Fatal(anyhow::anyhow!(
+            "file is larger than {} bytes",
+            max_size.unwrap_or_default()
+        ))
     };
-
-    let url = asset
-        .browser_download_url
-        .as_deref()
-        .ok_or("asset has no browser_download_url")?;
-    let resp = client.get(url).send().await?.error_for_status()?;
-
-    use futures_util::StreamExt;
+    if total.is_some_and(too_large) {
+        let _ = fs::remove_file(part).await;
+        return Err(too_large_error());
+    }
+    let mut written = if resumed { offset } else { 0 };
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(part)
+        .await
+        .map_err(|e| AttemptError::Fatal(e.into()))?;
     let mut stream = resp.bytes_stream();
-    let mut file = File::create(join_path("bin_", app_name)).await?;
     while let Some(chunk) = stream.next().await {
-        file.write_all(&chunk?).await?;
+        let chunk = chunk.map_err(|e| AttemptError::Retry(e.into()))?;
+        written += chunk.len() as u64;
+        if too_large(written) {
+            drop(file);
+            let _ = fs::remove_file(part).await;
+            return Err(too_large_error());
+        }
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| AttemptError::Fatal(e.into()))?;
     }
-    file.flush().await?;
-    Ok(())
+    file.flush()
+        .await
+        .map_err(|e| AttemptError::Fatal(e.into()))?;
+    Ok(total)
 }
 
-/// Асинхронно загружает релиз ffmpeg с GitHub для указанного app_name:
-/// - если файл с именем app_name уже существует — пропускает работу;
-/// - поведение загрузки аналогично fetch_ytdlp_release_async (GitHub API, фильтрация assets);
-/// - скачивает выбранный asset потоково и сохраняет в файл app_name;
-/// - затем распаковывает из ожидаемого ZIP "ffmpeg-master-latest-win64-lgpl-shared.zip"
-///   все файлы с префиксом "ffmpeg-master-latest-win64-lgpl-shared/bin/" в каталог "bin_".
-/// Возвращает ошибки при сетевых, файловых или распаковочных сбоях.
-pub async fn fetch_ffmpeg_release_async(
-    app_name: &str,
-    github_api: &str,
-) -> anyhow::Result<(), Box<dyn Error + Send + Sync>> {
-    if fs::metadata(app_name).await.is_ok() {
-        return Ok(());
-    }
-
-    println!("downloading ffmpeg for {}", app_name);
-    println!("{}", github_api);
-
-    let client = reqwest::Client::new();
-    let mut req = client
-        .get(github_api)
-        .header(USER_AGENT, "gh-download-rust/0.1")
-        .header(ACCEPT, "application/vnd.github.v3+json");
-
-    if let Ok(token) = std::env::var("GITHUB_TOKEN") {
-        req = req.header(AUTHORIZATION, format!("token {}", token));
+/// Скачивает `url` в `dest` надёжно:
+/// - данные пишутся в "<dest>.part"; если он остался от прерванной загрузки, докачка идёт с HTTP Range;
+/// - сетевые ошибки, 5xx и 429 повторяются с экспоненциальной паузой (до DOWNLOAD_ATTEMPTS попыток);
+/// - размер файла сверяется с `expected_size` (например, Asset::size) и с размером из ответа сервера;
+/// - файл больше `max_size` не скачивается до конца — загрузка прерывается с ошибкой;
+/// - только полный файл атомарно переименовывается в `dest`.
+///
+/// Возвращает размер файла в байтах.
+pub async fn download_file_async(
+    client: &reqwest::Client,
+    url: &str,
+    headers: &HeaderMap,
+    dest: &Path,
+    expected_size: Option<u64>,
+    max_size: Option<u64>,
+) -> anyhow::Result<u64> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent).await?;
     }
-
-    let releases: Vec<Release> = req.send().await?.error_for_status()?.json().await?;
-    let release = releases
-        .into_iter()
-        .find(|r| !r.draft)
-        .ok_or("No release found")?;
-
-    let mut assets: Vec<Asset> = release
-        .assets
-        .into_iter()
-        .filter(|a| a.name.to_lowercase().ends_with(&app_name.to_lowercase()))
-        .collect();
-    assets.sort_by_key(|a| std::cmp::Reverse(a.size.unwrap_or(0)));
-    let asset = match assets.into_iter().next() {
-        Some(a) => a,
-        None => return Ok(()),
-    };
-
-    let url = asset
-        .browser_download_url
-        .as_deref()
-        .ok_or("asset has no browser_download_url")?;
-    let resp = client.get(url).send().await?.error_for_status()?;
-    use futures_util::StreamExt;
-    let mut stream = resp.bytes_stream();
-    let mut file = File::create(app_name).await?;
-    while let Some(chunk) = stream.next().await {
-        file.write_all(&chunk?).await?;
+    let part = part_path(dest);
+    let mut delay = RETRY_BASE_DELAY;
+    let mut attempt = 1;
+    loop {
+        let offset = match fs::metadata(&part).await {
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        };
+        let error =
+            match download_attempt(client, url, headers, &part, offset, expected_size, max_size)
+                .await
+            {
+                Ok(reported) => {
+                    let len = fs::metadata(&part).await?.len();
+                    match expected_size.or(reported) {
+                        Some(expected) if expected != len => {
+                            // недокачка или лишние байты — начинаем заново
+                            let _ = fs::remove_file(&part).await;
+                            anyhow::anyhow!(
+                                "size mismatch: expected {} bytes, got {}",
+                                expected,
+                                len
+                            )
+                        }
+                        _ => {
+                            fs::rename(&part, dest).await?;
+                            return Ok(len);
+                        }
+                    }
+                }
+                Err(AttemptError::Fatal(e)) => return Err(e),
+                Err(AttemptError::Retry(e)) => e,
+            };
+        if attempt >= DOWNLOAD_ATTEMPTS {
+            return Err(error.context(format!("download failed after {} attempts", attempt)));
+        }
+        eprintln!(
+            "Download of {} failed ({:#}), retrying in {:?}",
+            url, error, delay
+        );
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(RETRY_MAX_DELAY);
+        attempt += 1;
     }
-    file.flush().await?;
-
-    let zip_path = Path::new("ffmpeg-master-latest-win64-lgpl-shared.zip");
-    let dest = Path::new("bin_");
-    let target_prefix = "ffmpeg-master-latest-win64-lgpl-shared/bin/";
-    extract_prefix_from_zip(zip_path, dest, target_prefix)
-        .map_err(|e| format!("Ошибка при распаковке: {}", e))?;
+}
 
-    Ok(())
+/// Строит путь внутри каталога загрузок через confine_to_base;
+/// попытка выйти за его пределы превращается в PipelineError::Path.
+fn confined_path(base: &Path, relative: &Path) -> Result<PathBuf, PipelineError> {
+    confine_to_base(base, relative).map_err(|e| match e.kind() {
+        io::ErrorKind::PermissionDenied => PipelineError::Path(e.to_string()),
+        _ => PipelineError::Io(e),
+    })
 }
 
-/// Асинхронно обрабатывает строку из двух сегментов: первый должен начинаться с "image:", второй — с "yt-dlp:".
-/// - извлекает URL изображения и команду yt-dlp;
-/// - запускает yt-dlp (run_and_log) для скачивания аудиофайла;
-/// - определяет путь к выходному файлу, формирует путь к изображению (в том числе относительный -> домашняя папка);
-/// - скачивает баннер (download_banner_image_async), затем вызывает set_title_with_ffmpeg для установки обложки/тегов;
-/// - переименовывает временный файл в итоговый.
-/// При ошибках печатает сообщения и корректно возвращает/обрабатывает ошибки ввода-вывода.
+/// Асинхронно скачивает и тегирует трек по разобранному запросу:
+/// - вычисляет пути трека, обложки (.jpeg) и data.json и проверяет, что все они внутри `base`;
+/// - запускает yt-dlp (spawn_and_log_io) с аргументами из build_ytdlp_args;
+/// - проверяет код выхода yt-dlp и наличие итогового файла;
+/// - по options.loudness измеряет громкость (ReplayGain) или нормализует её (loudness::process_loudness);
+/// - сохраняет metadata запроса и результаты измерения громкости в data.json рядом с файлом;
+/// - скачивает баннер (download_banner_image_async) и записывает метаданные трека
+///   (DownloadRequest::track_metadata) и обложку прямо в файл (tag_writer::write_tags),
+///   без повторного запуска ffmpeg и перекодирования.
+///
+/// Этапы отражаются в состоянии задачи `job`; при её отмене текущий процесс убивается.
+/// Каждый сбой возвращается как соответствующий вариант PipelineError.
 pub async fn process_and_tag_sound_async(
-    image: &str,
-    yt_dlp: &str,
-    json_data: &str,
-) -> anyhow::Result<(), std::io::Error> {
-    let (image, ytdlp, json) = {
-        let a = image.trim_start();
-        let b = yt_dlp.trim_start();
-        let c = json_data.trim_start();
-        if a.starts_with("image:") && b.starts_with("yt-dlp:") {
-            (
-                a["image:".len()..].trim_start(),
-                b["yt-dlp:".len()..].trim_start(),
-                c["json-data:".len()..].trim_start(),
-            )
-        } else {
-            eprintln!("Invalid segments for download_sound");
-            return Ok(());
-        }
+    request: &DownloadRequest,
+    base: &Path,
+    job: &JobHandle,
+) -> Result<(), PipelineError> {
+    let output_file = PathBuf::from(request.output_file());
+    // обложка рядом с треком: "Track.opus" -> "Track.jpeg"
+    // (расширение options.output совпадает с audio_format, см. validate_options)
+    let cover_file = output_file.with_extension("jpeg");
+    let data_file = output_file.with_file_name("data.json");
+
+    let out_path = confined_path(base, &output_file)?
+        .to_string_lossy()
+        .into_owned();
+    let img_path = confined_path(base, &cover_file)?;
+    let data_path = confined_path(base, &data_file)?;
+
+    job.set_state(JobState::Downloading);
+    let ytdlp = resolve(YTDLP)
+        .ok_or_else(|| PipelineError::YtDlp(format!("yt-dlp not found in {} or PATH", BIN_DIR)))?;
+    let ffmpeg = resolve(FFMPEG)
+        .ok_or_else(|| PipelineError::Ffmpeg(format!("ffmpeg not found in {} or PATH", BIN_DIR)))?;
+    let network = network_args(Config::get_unwrap());
+    let ytdlp_args = build_ytdlp_args(
+        &request.options,
+        &ffmpeg,
+        &network,
+        &out_path,
+        &request.media_url,
+    );
+    let cancel = job.cancel.clone();
+    let job_id = job.id;
+    let ytdlp_errors = Arc::new(Mutex::new(Vec::new()));
+    let on_line = {
+        let ytdlp_errors = ytdlp_errors.clone();
+        Arc::new(move |line: &str| {
+            if let Some(event) = parse_ytdlp_progress(job_id, line) {
+                progress::publish(event);
+            } else if line.starts_with("ERROR:") {
+                ytdlp_errors.lock().unwrap().push(line.to_string());
+            }
+        })
     };
-
-    if let Err(e) = spawn_and_log_io("bin_/yt-dlp.exe", ytdlp) {
-        eprintln!("failed to run yt-dlp: {}", e);
-        return Ok(());
+    let ytdlp_result = tokio::task::spawn_blocking(move || {
+        spawn_and_log_io(&ytdlp.to_string_lossy(), &ytdlp_args, &cancel, on_line)
+    })
+    .await
+    .map_err(io::Error::other)?;
+    job.check_cancelled()?;
+    match ytdlp_result {
+        Ok(0) => {}
+        Ok(code) => {
+            let errors = ytdlp_errors.lock().unwrap().join("\n");
+            return Err(PipelineError::YtDlp(format!(
+                "exited with code {}: {}",
+                code, errors
+            )));
+        }
+        Err(e) => return Err(PipelineError::YtDlp(format!("failed to run: {}", e))),
+    }
+    if !Path::new(&out_path).is_file() {
+        return Err(PipelineError::YtDlp(format!(
+            "output file not found: {}",
+            out_path
+        )));
     }
 
-    let out_path =
-        match extract_output_path(ytdlp).and_then(|p| p.to_str().map(ToString::to_string)) {
-            Some(p) => p,
-            None => {
-                eprintln!("Could not determine output path");
-                return Ok(());
-            }
-        };
+    let options = &request.options;
+    let loudness = if options.loudness == LoudnessMode::Off {
+        None
+    } else {
+        job.set_state(JobState::Loudness);
+        let (path, cancel) = (PathBuf::from(&out_path), job.cancel.clone());
+        let (mode, target_lufs) = (options.loudness, options.target_lufs);
+        let encoder = audio_encoder(&options.audio_format);
+        tokio::task::spawn_blocking(move || {
+            process_loudness(&ffmpeg, &path, mode, target_lufs, encoder, &cancel)
+        })
+        .await
+        .map_err(io::Error::other)?
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::Interrupted => PipelineError::Cancelled,
+            _ => PipelineError::Ffmpeg(format!("loudness {}", e)),
+        })?
+    };
 
-    let img_path = {
-        let base = if out_path.ends_with(".mp3") {
-            out_path.trim_end_matches(".mp3").to_owned() + ".jpeg"
-        } else {
-            out_path.clone() + ".jpeg"
-        };
-        let p = Path::new(&base);
-        if let Some(dir) = p.parent() {
-            let file_path = dir.join(format!("{}.json", "data"));
-
-            fs::write(&file_path, json).await.unwrap_or_else(|e| {
-                panic!(
-                    "Failed to write JSON to file '{}': {}",
-                    file_path.display(),
-                    e
-                )
-            });
-
-            println!("parent dir: {}", dir.display());
-        } else {
-            println!("No parent directory (path has no parent)");
-        }
+    let mut data = request.metadata.clone();
+    data.entry("safeArtist")
+        .or_insert_with(|| request.artist.clone().into());
+    data.entry("safeTitle")
+        .or_insert_with(|| request.title.clone().into());
+    data.entry("image")
+        .or_insert_with(|| request.image_url.clone().into());
+    if let Some(report) = &loudness {
+        data.insert(
+            "loudness".to_string(),
+            serde_json::to_value(report).map_err(io::Error::other)?,
+        );
+    }
+    let json = serde_json::to_string_pretty(&data).map_err(io::Error::other)?;
+    fs::write(&data_path, &json).await?;
+    println!("Saved metadata: {}", data_path.display());
+    let image = request.image_url.as_str();
 
-        if p.is_absolute() {
-            p.to_path_buf()
-        } else {
-            dirs::home_dir()
-                .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "home dir"))?
-                .join(p)
-        }
-    };
+    job.set_output_path(&out_path);
+    job.set_state(JobState::Cover);
 
     let full_path_image = download_banner_image_async(
         image,
         img_path
             .to_str()
-            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid path"))?,
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid path"))?,
     )
     .await
-    .map_err(|e| {
-        std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("banner download failed: {}", e),
-        )
-    })?;
-
-    let tmp = format!("{}_t.mp3", out_path);
-    let file_name = Path::new(&out_path)
-        .file_name()
-        .and_then(|s| s.to_str())
-        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid filename"))?;
-
-    embed_title_and_artwork_with_ffmpeg(
-        r"bin_\ffmpeg.exe",
-        &out_path,
-        &tmp,
-        file_name,
-        full_path_image,
-    )
-    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("ffmpeg failed: {}", e)))?;
+    .map_err(|e| PipelineError::Cover(e.to_string()))?;
 
-    let _ = remove_and_rename(out_path.as_ref(), tmp.as_ref()).await;
+    job.check_cancelled()?;
+    job.set_state(JobState::Tagging);
+    if TagFormat::from_path(Path::new(&out_path)).is_none() {
+        println!("Skipping tags: {} has no supported tag format", out_path);
+        return Ok(());
+    }
+    let mut metadata = request.track_metadata();
+    metadata.replay_gain = loudness.and_then(|report| report.replay_gain);
+    let cover = fs::read(&full_path_image).await?;
+    let path = PathBuf::from(&out_path);
+    tokio::task::spawn_blocking(move || write_tags(&path, &metadata, Some(&cover)))
+        .await
+        .map_err(io::Error::other)?
+        .map_err(|e| PipelineError::Tagging(format!("{:#}", e)))?;
+    println!("Tagged: {}", out_path);
     Ok(())
 }
 
 /// Асинхронно скачивает изображение по заданному URL в указанный путь:
-/// - убирает кавычки вокруг URL, делает GET запрос с заголовками User-Agent и Referer;
-/// - проверяет HTTP-статус, потоково записывает содержимое в файл;
+/// - убирает кавычки вокруг URL, делает GET запрос общим клиентом (прокси, таймауты, User-Agent
+///   из конфига) с Referer из Config::cover_referer;
+/// - качает через download_file_async (докачка, повторы, атомарное переименование) в "<path>.download",
+///   не больше COVER_MAX_BYTES;
+/// - проверяет, что пришла картинка, и приводит её к JPEG по настройкам обложек (cover_art::process_cover);
 /// - возвращает строку с путём к сохранённому файлу или ошибку при сбое.
 pub async fn download_banner_image_async(url_img: &str, path: &str) -> anyhow::Result<String> {
     let url = url_img.trim_matches('"');
+    let client = http_client()?;
+    let config = Config::get()?;
 
-    let client = reqwest::Client::builder()
-        .danger_accept_invalid_certs(false)
-        .build()?;
-
-    let resp = client
-        .get(url)
-        .header(
-            USER_AGENT,
-            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/138.0.0.0 Safari/537.36",
-        )
-        .header(REFERER, "https://vk.com/")
-        .send()
-        .await?;
-
-    if !resp.status().is_success() {
-        anyhow::bail!("HTTP error: {}", resp.status());
-    }
-
-    use futures_util::StreamExt;
-    let mut stream = resp.bytes_stream();
-    let mut file = tokio::fs::File::create(path).await?;
-
-    while let Some(chunk) = stream.next().await {
-        let b = chunk?;
-        file.write_all(&b).await?;
+    let mut headers = HeaderMap::new();
+    if let Some(referer) = &config.cover_referer {
+        headers.insert(REFERER, HeaderValue::from_str(referer)?);
     }
+    let raw = PathBuf::from(format!("{}.download", path));
+    download_file_async(client, url, &headers, &raw, None, Some(COVER_MAX_BYTES)).await?;
+    let bytes = fs::read(&raw).await;
+    let _ = fs::remove_file(&raw).await;
+    let options = CoverOptions::from_config(config);
+    let jpeg = tokio::task::spawn_blocking(move || process_cover(&bytes?, options)).await??;
+    fs::write(path, jpeg).await?;
 
     println!("Saved image: {}", path);
 
     Ok(path.to_string())
 }
 
-/// Обрабатывает входную строку командой вида: image:"url"; yt-dlp ...
-/// - делит строку по ';', ожидает ровно два непустых сегмента;
-/// - если форматы сегментов верны, печатает их и вызывает process_and_tag_sound_async;
-/// - в противном случае печатает сообщение об ошибке формата.
-pub async fn handle_sound_command_async(raw_input: &str, download_path_base: &str) {
-    let mut segments: Vec<&str> = raw_input
-        .split(';')
-        .map(|s| s.trim())
-        .filter(|s| !s.is_empty())
-        .collect();
-
-    if segments.len() != 3 {
-        eprintln!(
-            "Error: the string must contain exactly one ';' (example: image:\"url\"; yt-dlp ...)."
-        );
-        return;
+/// Обрабатывает разобранный запрос на скачивание:
+/// - раскрывает `~` в `download_path_base` и канонизирует его — это каталог, куда пишутся файлы;
+/// - вызывает process_and_tag_sound_async для скачивания и тегирования трека;
+/// - после успешной обработки пересобирает индекс soundall.json (collect_sb) в том же каталоге.
+pub async fn handle_download_request_async(
+    request: &DownloadRequest,
+    download_path_base: &str,
+    job: &JobHandle,
+) -> Result<(), PipelineError> {
+    println!("\"artist\": {}", request.artist);
+    println!("\"title\": {}", request.title);
+    println!("\"media_url\": {}", request.media_url);
+
+    let base = std::fs::canonicalize(shellexpand::tilde(download_path_base).as_ref())?;
+    process_and_tag_sound_async(request, &base, job).await?;
+
+    job.set_state(JobState::Indexing);
+    tokio::task::spawn_blocking(move || collect_sb(&base))
+        .await
+        .map_err(io::Error::other)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{header_exists, method};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const BODY: &[u8] = b"0123456789abcdef";
+
+    async fn server_with_416(content_range: Option<&str>) -> MockServer {
+        let server = MockServer::start().await;
+        let mut not_satisfiable = ResponseTemplate::new(416);
+        if let Some(range) = content_range {
+            not_satisfiable = not_satisfiable.insert_header("Content-Range", range);
+        }
+        Mock::given(method("GET"))
+            .and(header_exists("range"))
+            .respond_with(not_satisfiable)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(BODY))
+            .mount(&server)
+            .await;
+        server
     }
 
-    let image = segments[0];
-    let mut yt_dlp = segments[1];
-    let json_data = segments[2];
-
-    if image.starts_with("image")
-        && yt_dlp.starts_with("yt-dlp")
-        && json_data.starts_with("json-data")
-    {
-        println!("\"image\": {}", image);
-        println!("\"yt-dlp\": {}", yt_dlp);
-        println!("\"json-data\": {}", json_data);
-
-        let fyt_dlp = if let Some(pos) = yt_dlp.rfind("-o \"") {
-            if let Some(quote_end) = yt_dlp[pos + 4..].find('"') {
-                let insert_pos = pos + 4;
-                let mut result = yt_dlp.to_string();
-                result.insert_str(insert_pos, &format!("{}/", download_path_base));
-                result
-            } else {
-                yt_dlp.to_string()
-            }
-        } else {
-            yt_dlp.to_string()
-        };
+    #[tokio::test]
+    async fn range_not_satisfiable_restarts_a_stale_part() {
+        let server = server_with_416(None).await;
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("file.bin");
+        std::fs::write(part_path(&dest), b"stale bytes from another file").unwrap();
 
-        process_and_tag_sound_async(image, fyt_dlp.as_str(), json_data)
+        let client = reqwest::Client::new();
+        let url = format!("{}/file.bin", server.uri());
+        let len = download_file_async(&client, &url, &HeaderMap::new(), &dest, None, None)
             .await
-            .expect(&format!(
-                "Failed to process and tag sound for image='{}' yt_dlp='{}' json_data='{}'",
-                image, fyt_dlp, json_data
-            ));
+            .unwrap();
+        assert_eq!(len, BODY.len() as u64);
+        assert_eq!(std::fs::read(&dest).unwrap(), BODY);
+        assert!(!part_path(&dest).exists());
+    }
 
-        collect_sb(download_path_base);
-    } else {
-        println!(
-            "The first argument or the second argument does not match the expected format:\n  first = {}\n  second = {}",
-            image, yt_dlp
+    #[tokio::test]
+    async fn range_not_satisfiable_completes_a_full_part() {
+        let server = server_with_416(Some(&format!("bytes */{}", BODY.len()))).await;
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("file.bin");
+        std::fs::write(part_path(&dest), BODY).unwrap();
+
+        let client = reqwest::Client::new();
+        let url = format!("{}/file.bin", server.uri());
+        download_file_async(&client, &url, &HeaderMap::new(), &dest, None, None)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&dest).unwrap(), BODY);
+        let requests = server.received_requests().await.unwrap();
+        assert_eq!(requests.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn download_stops_at_max_size() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0u8; 4096]))
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("cover.download");
+
+        let client = reqwest::Client::new();
+        let url = format!("{}/cover.jpg", server.uri());
+        let error = download_file_async(&client, &url, &HeaderMap::new(), &dest, None, Some(1024))
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("larger than 1024 bytes"),
+            "{}",
+            error
         );
+        assert!(!dest.exists());
+        assert!(!part_path(&dest).exists());
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
     }
 }
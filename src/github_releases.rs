@@ -0,0 +1,533 @@
+use crate::config_manager::Config;
+use crate::structures::structs_git::{Asset, Release};
+use anyhow::{Context, bail};
+use regex::Regex;
+use reqwest::StatusCode;
+use reqwest::header::{ACCEPT, AUTHORIZATION, HeaderMap, LINK, RETRY_AFTER};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+/// Релизов на страницу (максимум GitHub API) и сколько страниц просматривать
+const PER_PAGE: u32 = 100;
+const MAX_PAGES: u32 = 10;
+
+/// Сколько раз ждать сброса лимита запросов, прежде чем сдаться
+const RATE_LIMIT_RETRIES: u32 = 2;
+
+/// Какие релизы рассматривать при выборе.
+#[derive(Debug, Clone, Copy)]
+pub struct ReleasePolicy {
+    /// Учитывать prerelease-релизы (nightly и т.п.); черновики не учитываются никогда
+    pub include_prereleases: bool,
+}
+
+/// Куда обращаться к GitHub API и сколько ждать сброса лимита запросов.
+#[derive(Debug, Clone)]
+pub struct GithubApi {
+    /// Базовый адрес без завершающего '/'
+    pub base_url: String,
+    pub max_rate_limit_wait: Duration,
+}
+
+impl GithubApi {
+    /// Настройки из Config::github_api_url и Config::github_rate_limit_wait_secs.
+    pub fn from_config(config: &Config) -> GithubApi {
+        GithubApi {
+            base_url: config.github_api_url.trim_end_matches('/').to_string(),
+            max_rate_limit_wait: Duration::from_secs(config.github_rate_limit_wait_secs),
+        }
+    }
+}
+
+/// GET-запрос к GitHub API с нужными заголовками и GITHUB_TOKEN, если он задан
+/// (User-Agent, который требует GitHub, задаёт общий клиент).
+fn github_get(client: &reqwest::Client, url: &str) -> reqwest::RequestBuilder {
+    let mut req = client
+        .get(url)
+        .header(ACCEPT, "application/vnd.github.v3+json");
+    if let Ok(token) = std::env::var("GITHUB_TOKEN") {
+        req = req.header(AUTHORIZATION, format!("token {}", token));
+    }
+    req
+}
+
+/// Сколько ждать, если ответ — превышение лимита запросов (403/429 с X-RateLimit-Remaining: 0
+/// или с Retry-After). None — это не лимит, а обычная ошибка.
+fn rate_limit_delay(status: StatusCode, headers: &HeaderMap) -> Option<Duration> {
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+    };
+    if let Some(secs) = header(RETRY_AFTER.as_str()) {
+        return Some(Duration::from_secs(secs));
+    }
+    if header("x-ratelimit-remaining") != Some(0) {
+        return None;
+    }
+    let reset = header("x-ratelimit-reset")?;
+    let now = chrono::Utc::now().timestamp().max(0) as u64;
+    // +1 с: сброс происходит в начале указанной секунды
+    Some(Duration::from_secs(reset.saturating_sub(now) + 1))
+}
+
+/// Ссылка на следующую страницу из заголовка Link (`<url>; rel="next"`).
+fn next_page(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(LINK)?
+        .to_str()
+        .ok()?
+        .split(',')
+        .find(|part| part.contains("rel=\"next\""))
+        .and_then(|part| {
+            let start = part.find('<')? + 1;
+            let end = part.find('>')?;
+            part.get(start..end).map(str::to_string)
+        })
+}
+
+/// Запрашивает `url` и разбирает JSON. При превышении лимита запросов ждёт его сброса,
+/// если ожидание не дольше GithubApi::max_rate_limit_wait, иначе возвращает понятную ошибку.
+/// Возвращает данные и ссылку на следующую страницу.
+async fn get_json<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    api: &GithubApi,
+    url: &str,
+) -> anyhow::Result<(T, Option<String>)> {
+    let max_wait = api.max_rate_limit_wait;
+    let mut retries = 0;
+    loop {
+        let resp = github_get(client, url).send().await?;
+        let status = resp.status();
+        if let Some(delay) = rate_limit_delay(status, resp.headers()) {
+            if delay > max_wait || retries >= RATE_LIMIT_RETRIES {
+                bail!(
+                    "GitHub API rate limit exceeded, resets in {}s; set GITHUB_TOKEN to raise the limit",
+                    delay.as_secs()
+                );
+            }
+            eprintln!(
+                "GitHub API rate limit exceeded, waiting {}s",
+                delay.as_secs()
+            );
+            tokio::time::sleep(delay).await;
+            retries += 1;
+            continue;
+        }
+        if status == StatusCode::NOT_FOUND {
+            bail!(
+                "{} returned 404 Not Found (unknown repository or tag?)",
+                url
+            );
+        }
+        let resp = resp.error_for_status()?;
+        let next = next_page(resp.headers());
+        let body = resp
+            .json()
+            .await
+            .with_context(|| format!("invalid response from {}", url))?;
+        return Ok((body, next));
+    }
+}
+
+/// Самый крупный asset релиза, имя которого подходит под `matcher`.
+fn best_asset<'a>(release: &'a Release, matcher: &Regex) -> Option<&'a Asset> {
+    release
+        .assets
+        .iter()
+        .filter(|a| matcher.is_match(&a.name))
+        .max_by_key(|a| a.size.unwrap_or(0))
+}
+
+/// Находит релиз `repo` и asset в нём:
+/// - `pin` — релиз с этим тегом (политика prerelease к нему не применяется);
+/// - иначе релизы просматриваются постранично от новых к старым, пропуская черновики и,
+///   по политике, prerelease; выбирается первый релиз, где есть подходящий под `matcher` asset.
+pub async fn find_release_asset_async(
+    client: &reqwest::Client,
+    api: &GithubApi,
+    repo: &str,
+    matcher: &Regex,
+    policy: ReleasePolicy,
+    pin: Option<&str>,
+) -> anyhow::Result<(Release, Asset)> {
+    let releases_url = format!("{}/repos/{}/releases", api.base_url, repo);
+    if let Some(tag) = pin {
+        let url = format!("{}/tags/{}", releases_url, urlencoding::encode(tag));
+        let (release, _): (Release, _) = get_json(client, api, &url)
+            .await
+            .with_context(|| format!("release {} of {}", tag, repo))?;
+        let asset = best_asset(&release, matcher)
+            .with_context(|| format!("no asset matching {} in release {}", matcher, tag))?
+            .clone();
+        return Ok((release, asset));
+    }
+
+    let mut url = Some(format!("{}?per_page={}", releases_url, PER_PAGE));
+    let mut pages = 0;
+    let mut considered = 0;
+    while let Some(page) = url.take() {
+        if pages == MAX_PAGES {
+            break;
+        }
+        pages += 1;
+        let (releases, next): (Vec<Release>, _) = get_json(client, api, &page).await?;
+        for release in releases {
+            if release.draft || (release.prerelease && !policy.include_prereleases) {
+                continue;
+            }
+            considered += 1;
+            if let Some(asset) = best_asset(&release, matcher) {
+                let asset = asset.clone();
+                return Ok((release, asset));
+            }
+        }
+        url = next;
+    }
+    if considered == 0 {
+        bail!("no published releases in {}", repo);
+    }
+    bail!(
+        "no asset matching {} in the last {} releases of {}",
+        matcher,
+        considered,
+        repo
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool_spec::{AssetRule, ToolSource, ToolSpec};
+    use reqwest::header::HeaderValue;
+    use serde_json::{Value, json};
+    use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const RELEASES: &str = "/repos/owner/tool/releases";
+
+    fn client() -> reqwest::Client {
+        reqwest::Client::builder().no_proxy().build().unwrap()
+    }
+
+    fn api(server: &MockServer, max_wait_secs: u64) -> GithubApi {
+        GithubApi {
+            base_url: server.uri(),
+            max_rate_limit_wait: Duration::from_secs(max_wait_secs),
+        }
+    }
+
+    fn release(tag: &str, prerelease: bool, assets: &[(&str, u64)]) -> Value {
+        let assets: Vec<Value> = assets
+            .iter()
+            .enumerate()
+            .map(|(id, (name, size))| {
+                json!({
+                    "url": format!("https://api.example/assets/{}", id),
+                    "id": id,
+                    "name": name,
+                    "size": size,
+                    "browser_download_url": format!("https://example/{}/{}", tag, name),
+                })
+            })
+            .collect();
+        json!({ "tag_name": tag, "draft": false, "prerelease": prerelease, "assets": assets })
+    }
+
+    fn draft(tag: &str, assets: &[(&str, u64)]) -> Value {
+        let mut release = release(tag, false, assets);
+        release["draft"] = json!(true);
+        release
+    }
+
+    async fn find(
+        server: &MockServer,
+        pattern: &str,
+        include_prereleases: bool,
+        pin: Option<&str>,
+    ) -> anyhow::Result<(Release, Asset)> {
+        let policy = ReleasePolicy {
+            include_prereleases,
+        };
+        let matcher = Regex::new(pattern).unwrap();
+        find_release_asset_async(
+            &client(),
+            &api(server, 5),
+            "owner/tool",
+            &matcher,
+            policy,
+            pin,
+        )
+        .await
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[tokio::test]
+    async fn follows_link_header_to_next_page() {
+        let server = MockServer::start().await;
+        let next = format!("{}{}?per_page=100&page=2", server.uri(), RELEASES);
+        Mock::given(method("GET"))
+            .and(path(RELEASES))
+            .and(query_param_is_missing("page"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header(
+                        "link",
+                        format!("<{}>; rel=\"next\", <{}>; rel=\"last\"", next, next),
+                    )
+                    .set_body_json(json!([
+                        draft("v3", &[("tool_linux", 10)]),
+                        release("v2", false, &[("tool_windows.exe", 10)]),
+                    ])),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(RELEASES))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([release(
+                "v1",
+                false,
+                &[("tool_linux", 20)]
+            )])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let (release, asset) = find(&server, "^tool_linux$", false, None).await.unwrap();
+        assert_eq!(release.tag_name.as_deref(), Some("v1"));
+        assert_eq!(asset.name, "tool_linux");
+    }
+
+    #[tokio::test]
+    async fn skips_prereleases_unless_the_policy_allows_them() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(RELEASES))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+                release("v2-nightly", true, &[("tool_linux", 10)]),
+                release("v1", false, &[("tool_linux", 10)]),
+            ])))
+            .mount(&server)
+            .await;
+
+        let (stable, _) = find(&server, "^tool_linux$", false, None).await.unwrap();
+        assert_eq!(stable.tag_name.as_deref(), Some("v1"));
+        let (nightly, _) = find(&server, "^tool_linux$", true, None).await.unwrap();
+        assert_eq!(nightly.tag_name.as_deref(), Some("v2-nightly"));
+    }
+
+    #[tokio::test]
+    async fn pinned_tag_is_fetched_directly() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/tags/2024.01.01", RELEASES)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(release(
+                "2024.01.01",
+                true,
+                &[("tool_linux", 10)],
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let (release, asset) = find(&server, "^tool_linux$", false, Some("2024.01.01"))
+            .await
+            .unwrap();
+        assert_eq!(release.tag_name.as_deref(), Some("2024.01.01"));
+        assert_eq!(asset.name, "tool_linux");
+
+        let missing = find(&server, "^tool_linux$", false, Some("0.0.0")).await;
+        assert!(format!("{:#}", missing.unwrap_err()).contains("404"));
+    }
+
+    #[tokio::test]
+    async fn selects_largest_asset_matching_the_platform_rule() {
+        let spec = ToolSpec {
+            name: "tool".to_string(),
+            source: ToolSource::Github {
+                repo: "owner/tool".to_string(),
+            },
+            asset: None,
+            assets: vec![
+                AssetRule {
+                    pattern: "linux-arm64\\.tar\\.xz$".to_string(),
+                    os: Some("linux".to_string()),
+                    arch: Some("aarch64".to_string()),
+                },
+                AssetRule {
+                    pattern: "linux64.*\\.tar\\.xz$".to_string(),
+                    os: Some("linux".to_string()),
+                    arch: None,
+                },
+                AssetRule {
+                    pattern: "win64.*\\.zip$".to_string(),
+                    os: Some("Windows".to_string()),
+                    arch: Some("x86_64".to_string()),
+                },
+            ],
+            include_prereleases: false,
+            archive: None,
+            extract: Vec::new(),
+            target: None,
+            sha256: None,
+            allow_unverified: false,
+        };
+        assert_eq!(
+            spec.asset_pattern_for("linux", "aarch64"),
+            Some("linux-arm64\\.tar\\.xz$")
+        );
+        assert_eq!(
+            spec.asset_pattern_for("linux", "x86_64"),
+            Some("linux64.*\\.tar\\.xz$")
+        );
+        assert_eq!(
+            spec.asset_pattern_for("windows", "x86_64"),
+            Some("win64.*\\.zip$")
+        );
+        assert_eq!(spec.asset_pattern_for("macos", "aarch64"), None);
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(RELEASES))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([release(
+                "v1",
+                false,
+                &[
+                    ("tool-win64-gpl.zip", 300),
+                    ("tool-win64-lgpl.zip", 200),
+                    ("tool-win64-lgpl.zip.sha256", 64),
+                    ("tool-linux64-gpl.tar.xz", 500),
+                ],
+            )])))
+            .mount(&server)
+            .await;
+        let pattern = spec.asset_pattern_for("windows", "x86_64").unwrap();
+        let (_, asset) = find(&server, pattern, false, None).await.unwrap();
+        assert_eq!(asset.name, "tool-win64-gpl.zip");
+    }
+
+    #[tokio::test]
+    async fn waits_for_rate_limit_reset_and_retries() {
+        let server = MockServer::start().await;
+        let now = chrono::Utc::now().timestamp().to_string();
+        Mock::given(method("GET"))
+            .and(path(RELEASES))
+            .respond_with(
+                ResponseTemplate::new(403)
+                    .insert_header("x-ratelimit-remaining", "0")
+                    .insert_header("x-ratelimit-reset", now.as_str()),
+            )
+            .up_to_n_times(1)
+            .with_priority(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(RELEASES))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!([release(
+                "v1",
+                false,
+                &[("tool_linux", 10)]
+            )])))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let (release, _) = find(&server, "^tool_linux$", false, None).await.unwrap();
+        assert_eq!(release.tag_name.as_deref(), Some("v1"));
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_rate_limit_resets_too_late() {
+        let server = MockServer::start().await;
+        let reset = (chrono::Utc::now().timestamp() + 3600).to_string();
+        Mock::given(method("GET"))
+            .and(path(RELEASES))
+            .respond_with(
+                ResponseTemplate::new(403)
+                    .insert_header("x-ratelimit-remaining", "0")
+                    .insert_header("x-ratelimit-reset", reset.as_str()),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let error = find(&server, "^tool_linux$", false, None)
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("rate limit exceeded"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn rate_limit_delay_reads_reset_and_retry_after() {
+        let reset = (chrono::Utc::now().timestamp() + 30).to_string();
+        let exhausted = headers(&[
+            ("x-ratelimit-remaining", "0"),
+            ("x-ratelimit-reset", &reset),
+        ]);
+        let delay = rate_limit_delay(StatusCode::FORBIDDEN, &exhausted).unwrap();
+        assert!((29..=31).contains(&delay.as_secs()), "{:?}", delay);
+
+        let past = headers(&[("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", "1")]);
+        assert_eq!(
+            rate_limit_delay(StatusCode::TOO_MANY_REQUESTS, &past),
+            Some(Duration::from_secs(1))
+        );
+        let retry_after = headers(&[("retry-after", "7")]);
+        assert_eq!(
+            rate_limit_delay(StatusCode::TOO_MANY_REQUESTS, &retry_after),
+            Some(Duration::from_secs(7))
+        );
+    }
+
+    #[test]
+    fn rate_limit_delay_ignores_other_errors() {
+        let remaining = headers(&[("x-ratelimit-remaining", "42"), ("x-ratelimit-reset", "1")]);
+        assert_eq!(rate_limit_delay(StatusCode::FORBIDDEN, &remaining), None);
+        assert_eq!(
+            rate_limit_delay(StatusCode::FORBIDDEN, &HeaderMap::new()),
+            None
+        );
+        let exhausted = headers(&[("x-ratelimit-remaining", "0"), ("x-ratelimit-reset", "1")]);
+        assert_eq!(rate_limit_delay(StatusCode::OK, &exhausted), None);
+        assert_eq!(rate_limit_delay(StatusCode::NOT_FOUND, &exhausted), None);
+    }
+
+    #[test]
+    fn next_page_reads_link_header() {
+        let link = headers(&[(
+            "link",
+            "<https://api.github.com/repositories/1/releases?page=1>; rel=\"prev\", \
+             <https://api.github.com/repositories/1/releases?page=3>; rel=\"next\", \
+             <https://api.github.com/repositories/1/releases?page=9>; rel=\"last\"",
+        )]);
+        assert_eq!(
+            next_page(&link).as_deref(),
+            Some("https://api.github.com/repositories/1/releases?page=3")
+        );
+
+        let last = headers(&[(
+            "link",
+            "<https://api.github.com/repositories/1/releases?page=1>; rel=\"first\"",
+        )]);
+        assert_eq!(next_page(&last), None);
+        assert_eq!(next_page(&HeaderMap::new()), None);
+    }
+}
@@ -0,0 +1,39 @@
+use crate::config_manager::Config;
+use anyhow::Context;
+use once_cell::sync::OnceCell;
+use std::fs;
+use std::time::Duration;
+
+/// Общий HTTP-клиент приложения, создаётся при первом обращении
+static CLIENT: OnceCell<reqwest::Client> = OnceCell::new();
+
+/// Собирает клиент по настройкам из Config: прокси (http/socks), таймауты
+/// подключения и чтения, User-Agent и дополнительные корневые сертификаты.
+fn build_client(config: &Config) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder()
+        .user_agent(&config.user_agent)
+        .connect_timeout(Duration::from_secs(config.connect_timeout_secs))
+        .read_timeout(Duration::from_secs(config.read_timeout_secs));
+
+    if let Some(proxy) = &config.proxy {
+        let proxy =
+            reqwest::Proxy::all(proxy).with_context(|| format!("invalid proxy {:?}", proxy))?;
+        builder = builder.proxy(proxy);
+    }
+    for path in &config.ca_certificates {
+        let pem = fs::read(path).with_context(|| format!("Failed to read certificate {}", path))?;
+        let certs = reqwest::Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("invalid PEM certificate {}", path))?;
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    builder.build().context("Failed to build HTTP client")
+}
+
+/// Общий клиент для загрузки инструментов и обложек.
+/// Ошибка настройки (неверный прокси, нечитаемый сертификат) возвращается при каждом вызове.
+pub fn http_client() -> anyhow::Result<&'static reqwest::Client> {
+    CLIENT.get_or_try_init(|| build_client(Config::get()?))
+}
@@ -0,0 +1,457 @@
+use crate::download_manager::handle_download_request_async;
+use crate::pipeline_error::{ErrorBody, PipelineError};
+use crate::process_manager::CancelToken;
+use crate::progress::{self, ProgressEvent};
+use crate::structures::download_request::DownloadRequest;
+use chrono::{DateTime, Utc};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+use tokio::sync::{Mutex, Notify, mpsc};
+
+/// Идентификатор задачи в очереди загрузок.
+pub type JobId = u64;
+
+/// Этап, на котором находится задача.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Downloading,
+    Loudness,
+    Cover,
+    Tagging,
+    Indexing,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    /// true для состояний, из которых задача уже не выйдет.
+    pub fn is_finished(self) -> bool {
+        matches!(
+            self,
+            JobState::Done | JobState::Failed | JobState::Cancelled
+        )
+    }
+}
+
+/// Публичный снимок задачи, отдаётся через GET /jobs и GET /jobs/{id}.
+#[derive(Debug, Clone, Serialize)]
+pub struct JobInfo {
+    pub id: JobId,
+    pub state: JobState,
+    pub request: DownloadRequest,
+    pub output_path: Option<String>,
+    pub error: Option<ErrorBody>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+struct JobEntry {
+    info: JobInfo,
+    cancel: CancelToken,
+    /// Сколько вызовов JobQueue::wait ждут задачу; такая задача не удаляется при очистке
+    waiters: usize,
+}
+
+/// Задача на скачивание: разобранный запрос клиента.
+pub struct Job {
+    pub id: JobId,
+    pub request: DownloadRequest,
+}
+
+/// Результат попытки отменить задачу через JobQueue::cancel().
+pub enum CancelOutcome {
+    Cancelled(JobInfo),
+    AlreadyFinished(JobInfo),
+    NotFound,
+}
+
+/// Сколько и как долго хранить завершённые задачи (Done/Failed/Cancelled).
+#[derive(Debug, Clone, Copy)]
+pub struct JobRetention {
+    /// Не больше стольких завершённых задач, лишние — самые старые — удаляются
+    pub max_finished: usize,
+    /// Завершённые задачи старше этого удаляются
+    pub max_age: Duration,
+}
+
+/// Очередь задач на скачивание с пулом фоновых обработчиков.
+/// Задачи принимаются мгновенно, а выполняются не более чем `workers` штук одновременно.
+pub struct JobQueue {
+    sender: mpsc::UnboundedSender<Job>,
+    next_id: AtomicU64,
+    jobs: StdMutex<BTreeMap<JobId, JobEntry>>,
+    finished: Notify,
+    retention: JobRetention,
+}
+
+/// Глобальная очередь задач, инициализируется один раз через JobQueue::start()
+static QUEUE: OnceCell<JobQueue> = OnceCell::new();
+
+/// Ссылка на выполняющуюся задачу, через которую конвейер скачивания сообщает о прогрессе
+/// и узнаёт об отмене.
+#[derive(Clone)]
+pub struct JobHandle {
+    pub id: JobId,
+    pub cancel: CancelToken,
+}
+
+impl JobHandle {
+    /// Переводит задачу в новое состояние.
+    pub fn set_state(&self, state: JobState) {
+        JobQueue::get().update(self.id, |info| info.state = state);
+        println!("[job {}] {:?}", self.id, state);
+    }
+
+    /// Возвращает PipelineError::Cancelled, если задача была отменена.
+    pub fn check_cancelled(&self) -> Result<(), PipelineError> {
+        if self.cancel.is_cancelled() {
+            return Err(PipelineError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Запоминает путь к итоговому файлу задачи.
+    pub fn set_output_path(&self, path: &str) {
+        JobQueue::get().update(self.id, |info| info.output_path = Some(path.to_string()));
+    }
+}
+
+impl JobQueue {
+    /// Создаёт глобальную очередь и запускает `workers` фоновых обработчиков в текущем tokio runtime.
+    /// Каждая задача обрабатывается через handle_download_request_async с базовым путём `download_path`;
+    /// завершённые задачи хранятся в пределах `retention`.
+    /// Возвращает ошибку, если очередь уже запущена.
+    pub fn start(
+        workers: usize,
+        download_path: &'static str,
+        retention: JobRetention,
+    ) -> anyhow::Result<()> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        QUEUE
+            .set(JobQueue {
+                sender,
+                next_id: AtomicU64::new(1),
+                jobs: StdMutex::new(BTreeMap::new()),
+                finished: Notify::new(),
+                retention,
+            })
+            .map_err(|_| anyhow::anyhow!("Job queue is already started"))?;
+
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = workers.max(1);
+        for worker in 0..workers {
+            tokio::spawn(worker_loop(worker, receiver.clone(), download_path));
+        }
+        println!("Job queue started with {} worker(s)", workers);
+        Ok(())
+    }
+
+    /// Возвращает глобальную очередь.
+    /// # Panics
+    /// Паникует, если JobQueue::start() ещё не вызывался
+    pub fn get() -> &'static JobQueue {
+        QUEUE
+            .get()
+            .expect("Job queue is not started. Call JobQueue::start() first")
+    }
+
+    /// Ставит запрос в очередь и сразу возвращает идентификатор задачи.
+    pub fn enqueue(&self, request: DownloadRequest) -> anyhow::Result<JobId> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let now = Utc::now();
+        let mut jobs = self.jobs.lock().unwrap();
+        self.prune(&mut jobs);
+        jobs.insert(
+            id,
+            JobEntry {
+                info: JobInfo {
+                    id,
+                    state: JobState::Queued,
+                    request: request.clone(),
+                    output_path: None,
+                    error: None,
+                    created_at: now,
+                    updated_at: now,
+                },
+                cancel: CancelToken::default(),
+                waiters: 0,
+            },
+        );
+        drop(jobs);
+
+        if self.sender.send(Job { id, request }).is_err() {
+            self.jobs.lock().unwrap().remove(&id);
+            anyhow::bail!("Job queue is closed");
+        }
+        println!("[job {}] queued", id);
+        Ok(id)
+    }
+
+    /// Возвращает снимки всех известных задач в порядке их создания.
+    pub fn list(&self) -> Vec<JobInfo> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .values()
+            .map(|e| e.info.clone())
+            .collect()
+    }
+
+    /// Возвращает снимок задачи по идентификатору.
+    pub fn info(&self, id: JobId) -> Option<JobInfo> {
+        self.jobs.lock().unwrap().get(&id).map(|e| e.info.clone())
+    }
+
+    /// Ожидает, пока задача перейдёт в конечное состояние, и возвращает её снимок.
+    /// Пока задачу ждут, prune её не удаляет, даже если она старше или лишняя по retention.
+    pub async fn wait(&self, id: JobId) -> Option<JobInfo> {
+        self.jobs.lock().unwrap().get_mut(&id)?.waiters += 1;
+        let _waiter = Waiter { queue: self, id };
+        loop {
+            let notified = self.finished.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let info = self.info(id)?;
+            if info.state.is_finished() {
+                return Some(info);
+            }
+            notified.await;
+        }
+    }
+
+    /// Отменяет задачу: ожидающая в очереди будет пропущена обработчиком,
+    /// у выполняющейся будет убит текущий процесс yt-dlp или ffmpeg.
+    pub fn cancel(&self, id: JobId) -> CancelOutcome {
+        let mut jobs = self.jobs.lock().unwrap();
+        let Some(entry) = jobs.get_mut(&id) else {
+            return CancelOutcome::NotFound;
+        };
+        if entry.info.state.is_finished() {
+            return CancelOutcome::AlreadyFinished(entry.info.clone());
+        }
+
+        entry.cancel.cancel();
+        entry.info.state = JobState::Cancelled;
+        entry.info.updated_at = Utc::now();
+        println!("[job {}] cancelled", id);
+        publish_phase(&entry.info);
+        self.finished.notify_waiters();
+        let info = entry.info.clone();
+        self.prune(&mut jobs);
+        CancelOutcome::Cancelled(info)
+    }
+
+    /// Удаляет завершённые задачи старше `retention.max_age` и самые старые сверх
+    /// `retention.max_finished`. Незавершённые задачи и задачи, которые ждёт wait, не трогаются.
+    fn prune(&self, jobs: &mut BTreeMap<JobId, JobEntry>) {
+        let max_age =
+            chrono::Duration::from_std(self.retention.max_age).unwrap_or(chrono::Duration::MAX);
+        let oldest_allowed = Utc::now()
+            .checked_sub_signed(max_age)
+            .unwrap_or(DateTime::<Utc>::MIN_UTC);
+        let prunable = |e: &JobEntry| e.info.state.is_finished() && e.waiters == 0;
+        jobs.retain(|_, e| !prunable(e) || e.info.updated_at >= oldest_allowed);
+
+        let finished: Vec<JobId> = jobs
+            .iter()
+            .filter(|(_, e)| prunable(e))
+            .map(|(&id, _)| id)
+            .collect();
+        let excess = finished.len().saturating_sub(self.retention.max_finished);
+        for id in &finished[..excess] {
+            jobs.remove(id);
+        }
+    }
+
+    /// Применяет изменение к задаче, если она ещё не отменена.
+    /// При смене состояния рассылает событие Phase подписчикам прогресса.
+    fn update(&self, id: JobId, f: impl FnOnce(&mut JobInfo)) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let Some(entry) = jobs.get_mut(&id) else {
+            return;
+        };
+        if entry.info.state == JobState::Cancelled {
+            return;
+        }
+        let previous = entry.info.state;
+        f(&mut entry.info);
+        entry.info.updated_at = Utc::now();
+        if entry.info.state != previous {
+            publish_phase(&entry.info);
+            if entry.info.state.is_finished() {
+                self.finished.notify_waiters();
+                self.prune(&mut jobs);
+            }
+        }
+    }
+
+    fn handle(&self, id: JobId) -> Option<JobHandle> {
+        self.jobs.lock().unwrap().get(&id).map(|e| JobHandle {
+            id,
+            cancel: e.cancel.clone(),
+        })
+    }
+}
+
+/// Счётчик JobEntry::waiters для одного вызова wait; уменьшается и при отмене ожидания
+/// (клиент ?wait=true отключился).
+struct Waiter<'a> {
+    queue: &'a JobQueue,
+    id: JobId,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if let Some(entry) = self.queue.jobs.lock().unwrap().get_mut(&self.id) {
+            entry.waiters -= 1;
+        }
+    }
+}
+
+fn publish_phase(info: &JobInfo) {
+    progress::publish(ProgressEvent::Phase {
+        job_id: info.id,
+        state: info.state,
+        error: info.error.clone(),
+    });
+}
+
+/// Цикл одного обработчика: забирает задачи из общего канала, пока он не закрыт.
+async fn worker_loop(
+    worker: usize,
+    receiver: Arc<Mutex<mpsc::UnboundedReceiver<Job>>>,
+    download_path: &'static str,
+) {
+    loop {
+        let job = receiver.lock().await.recv().await;
+        let Some(job) = job else {
+            break;
+        };
+
+        let queue = JobQueue::get();
+        let Some(handle) = queue.handle(job.id) else {
+            continue;
+        };
+        if handle.cancel.is_cancelled() {
+            continue;
+        }
+
+        println!("[job {}] started on worker {}", job.id, worker);
+        let result = handle_download_request_async(&job.request, download_path, &handle).await;
+        match result {
+            Ok(()) => handle.set_state(JobState::Done),
+            Err(PipelineError::Cancelled) => {}
+            Err(e) => {
+                eprintln!("[job {}] failed: {}", job.id, e);
+                queue.update(job.id, |info| {
+                    info.state = JobState::Failed;
+                    info.error = Some(e.to_body());
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(retention: JobRetention) -> JobQueue {
+        let (sender, _) = mpsc::unbounded_channel();
+        JobQueue {
+            sender,
+            next_id: AtomicU64::new(1),
+            jobs: StdMutex::new(BTreeMap::new()),
+            finished: Notify::new(),
+            retention,
+        }
+    }
+
+    fn entry(id: JobId, state: JobState, waiters: usize) -> JobEntry {
+        let now = Utc::now();
+        JobEntry {
+            info: JobInfo {
+                id,
+                state,
+                request: serde_json::from_value(serde_json::json!({
+                    "version": 2,
+                    "media_url": "https://example.com/track",
+                    "image_url": "https://example.com/cover.jpg",
+                    "artist": "Artist",
+                    "title": "Title",
+                    "options": {
+                        "audio_format": "mp3",
+                        "loudness": "off",
+                        "target_lufs": -14.0,
+                        "embed_metadata": true
+                    }
+                }))
+                .unwrap(),
+                output_path: None,
+                error: None,
+                created_at: now,
+                updated_at: now,
+            },
+            cancel: CancelToken::default(),
+            waiters,
+        }
+    }
+
+    #[test]
+    fn prune_keeps_unfinished_and_waited_jobs() {
+        let queue = queue(JobRetention {
+            max_finished: 1,
+            max_age: Duration::ZERO,
+        });
+        let mut jobs = BTreeMap::new();
+        jobs.insert(1, entry(1, JobState::Done, 1));
+        jobs.insert(2, entry(2, JobState::Failed, 0));
+        jobs.insert(3, entry(3, JobState::Downloading, 0));
+        jobs.insert(4, entry(4, JobState::Done, 0));
+        std::thread::sleep(Duration::from_millis(5));
+        queue.prune(&mut jobs);
+        assert_eq!(jobs.keys().copied().collect::<Vec<_>>(), [1, 3]);
+    }
+
+    #[test]
+    fn prune_drops_the_oldest_finished_jobs_beyond_the_limit() {
+        let queue = queue(JobRetention {
+            max_finished: 2,
+            max_age: Duration::from_secs(3600),
+        });
+        let mut jobs: BTreeMap<JobId, JobEntry> = (1..=4)
+            .map(|id| (id, entry(id, JobState::Done, 0)))
+            .collect();
+        queue.prune(&mut jobs);
+        assert_eq!(jobs.keys().copied().collect::<Vec<_>>(), [3, 4]);
+    }
+
+    #[tokio::test]
+    async fn wait_returns_a_job_finished_with_zero_ttl() {
+        let queue: &'static JobQueue = Box::leak(Box::new(queue(JobRetention {
+            max_finished: 1,
+            max_age: Duration::ZERO,
+        })));
+        queue
+            .jobs
+            .lock()
+            .unwrap()
+            .insert(1, entry(1, JobState::Downloading, 0));
+        let waiter = tokio::spawn(queue.wait(1));
+        while queue.jobs.lock().unwrap()[&1].waiters == 0 {
+            tokio::task::yield_now().await;
+        }
+        queue.update(1, |info| info.state = JobState::Done);
+        let info = waiter.await.unwrap().expect("waited job was pruned");
+        assert_eq!(info.state, JobState::Done);
+        assert_eq!(queue.jobs.lock().unwrap()[&1].waiters, 0);
+    }
+}
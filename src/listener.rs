@@ -0,0 +1,112 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+
+/// Файл, из которого расширение узнаёт фактический адрес сервера; лежит рядом с config.json
+pub const DISCOVERY_FILE: &str = "endpoint.json";
+
+/// Сколько следующих портов пробовать, если настроенный занят
+const PORT_FALLBACK_ATTEMPTS: u16 = 20;
+
+/// Фактические точки подключения сервера, записываются в DISCOVERY_FILE.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Endpoint {
+    /// Базовый URL HTTP API, если сервер слушает TCP
+    pub url: Option<String>,
+    pub host: Option<String>,
+    pub port: Option<u16>,
+    /// Путь к Unix domain socket, если он включён
+    pub unix_socket: Option<String>,
+    pub pid: u32,
+}
+
+/// Привязывает TCP-сокет к `host:port`; если порт занят, пробует следующие
+/// PORT_FALLBACK_ATTEMPTS портов. Прочие ошибки привязки возвращаются сразу.
+pub fn bind_tcp_with_fallback(host: &str, port: u16) -> io::Result<TcpListener> {
+    let mut last_error = None;
+    for candidate in port..=port.saturating_add(PORT_FALLBACK_ATTEMPTS) {
+        match TcpListener::bind((host, candidate)) {
+            Ok(listener) => {
+                if candidate != port {
+                    println!("Port {} is busy, listening on {} instead", port, candidate);
+                }
+                return Ok(listener);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AddrInUse => last_error = Some(e),
+            Err(e) => return Err(e),
+        }
+    }
+    Err(last_error.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("no free port from {}", port),
+        )
+    }))
+}
+
+/// Привязывает Unix domain socket к `path` с правами 0600.
+///
+/// - на месте `path` допускается только сокет от прошлого запуска: он удаляется, если к нему никто
+///   не подключён; занятый сокет или любой другой файл — ошибка;
+/// - сокет создаётся во временном каталоге 0700 рядом с `path` и переносится на место уже с
+///   правами 0600, поэтому другие пользователи не могут подключиться к нему ни на мгновение.
+#[cfg(unix)]
+pub fn bind_unix_socket(path: &Path) -> io::Result<std::os::unix::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!(
+                        "unix socket {} is in use by another process",
+                        path.display()
+                    ),
+                ));
+            }
+            std::fs::remove_file(path)?;
+        }
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a unix socket", path.display()),
+            ));
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid unix socket path"))?;
+    let mut staging = path.as_os_str().to_owned();
+    staging.push(format!(".{}.tmp", std::process::id()));
+    let staging = PathBuf::from(staging);
+    std::fs::DirBuilder::new().mode(0o700).create(&staging)?;
+    let staged = staging.join(file_name);
+    let result = UnixListener::bind(&staged).and_then(|listener| {
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&staging);
+    result
+}
+
+/// Записывает фактические адреса сервера в DISCOVERY_FILE.
+pub fn write_discovery_file(endpoint: &Endpoint) -> anyhow::Result<()> {
+    let json = serde_json::to_string_pretty(endpoint)?;
+    std::fs::write(DISCOVERY_FILE, json).context("Failed to write discovery file")?;
+    Ok(())
+}
+
+/// Удаляет DISCOVERY_FILE при остановке сервера, чтобы расширение не подключалось к старому адресу.
+pub fn remove_discovery_file() {
+    let _ = std::fs::remove_file(DISCOVERY_FILE);
+}
@@ -0,0 +1,278 @@
+use crate::process_manager::{CancelToken, spawn_and_log_io};
+use lofty::file::AudioFile;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Опорная громкость ReplayGain 2.0, LUFS
+const REPLAY_GAIN_REFERENCE_LUFS: f64 = -18.0;
+
+/// Допустимый диапазон целевой громкости loudnorm (параметр I), LUFS
+pub const TARGET_LUFS_RANGE: (f64, f64) = (-70.0, -5.0);
+
+/// Предел true peak (dBTP) и целевой диапазон громкости (LU) для loudnorm
+const TARGET_TRUE_PEAK: f64 = -1.5;
+const TARGET_LRA: f64 = 11.0;
+
+/// Обработка громкости после скачивания.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoudnessMode {
+    /// Ничего не делать
+    #[default]
+    Off,
+    /// Измерить громкость (EBU R128) и записать теги ReplayGain, звук не меняется
+    ReplayGain,
+    /// Привести громкость к целевой двухпроходным loudnorm (с перекодированием)
+    Normalize,
+}
+
+/// Результат анализа громкости (первый проход loudnorm).
+#[derive(Debug, Clone, Serialize)]
+pub struct LoudnessMeasurement {
+    /// Интегральная громкость, LUFS
+    pub integrated_lufs: f64,
+    /// True peak, dBTP
+    pub true_peak_dbtp: f64,
+    /// Диапазон громкости, LU
+    pub lra: f64,
+    /// Порог стробирования, LUFS
+    pub threshold_lufs: f64,
+    /// Поправка, которую loudnorm применит во втором проходе, LU
+    pub target_offset: f64,
+}
+
+/// Значения тегов ReplayGain для трека.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ReplayGain {
+    /// Усиление до опорных -18 LUFS, дБ
+    pub track_gain_db: f64,
+    /// Пиковая амплитуда (1.0 — полная шкала)
+    pub track_peak: f64,
+}
+
+/// Итог этапа громкости; сохраняется в data.json под ключом "loudness".
+#[derive(Debug, Clone, Serialize)]
+pub struct LoudnessReport {
+    pub mode: LoudnessMode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_lufs: Option<f64>,
+    pub measured: LoudnessMeasurement,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replay_gain: Option<ReplayGain>,
+}
+
+/// JSON, который печатает loudnorm с print_format=json (числа — строками).
+#[derive(Deserialize)]
+struct LoudnormJson {
+    input_i: String,
+    input_tp: String,
+    input_lra: String,
+    input_thresh: String,
+    target_offset: String,
+}
+
+impl LoudnessMeasurement {
+    pub fn replay_gain(&self) -> ReplayGain {
+        ReplayGain {
+            track_gain_db: REPLAY_GAIN_REFERENCE_LUFS - self.integrated_lufs,
+            track_peak: 10f64.powf(self.true_peak_dbtp / 20.0),
+        }
+    }
+}
+
+/// Параметры loudnorm для целевой громкости `target_lufs`.
+fn loudnorm_filter(target_lufs: f64) -> String {
+    format!(
+        "loudnorm=I={}:TP={}:LRA={}",
+        target_lufs, TARGET_TRUE_PEAK, TARGET_LRA
+    )
+}
+
+/// Запускает ffmpeg и возвращает его вывод построчно; ненулевой код выхода — ошибка.
+fn run_ffmpeg(ffmpeg: &Path, args: &[String], cancel: &CancelToken) -> io::Result<Vec<String>> {
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let on_line = {
+        let lines = lines.clone();
+        Arc::new(move |line: &str| lines.lock().unwrap().push(line.to_string()))
+    };
+    let code = spawn_and_log_io(&ffmpeg.to_string_lossy(), args, cancel, on_line)?;
+    let lines = std::mem::take(&mut *lines.lock().unwrap());
+    if code != 0 {
+        let tail = lines[lines.len().saturating_sub(5)..].join("\n");
+        return Err(io::Error::other(format!(
+            "ffmpeg exited with code {}: {}",
+            code, tail
+        )));
+    }
+    Ok(lines)
+}
+
+/// Разбирает последний JSON-блок loudnorm из вывода ffmpeg.
+fn parse_loudnorm(lines: &[String]) -> io::Result<LoudnessMeasurement> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let start = lines
+        .iter()
+        .rposition(|line| line.trim() == "{")
+        .ok_or_else(|| invalid("loudnorm printed no measurements"))?;
+    let end = start
+        + lines[start..]
+            .iter()
+            .position(|line| line.trim() == "}")
+            .ok_or_else(|| invalid("truncated loudnorm measurements"))?;
+    let json: LoudnormJson = serde_json::from_str(&lines[start..=end].join("\n"))
+        .map_err(|e| invalid(&format!("invalid loudnorm measurements: {}", e)))?;
+    let number = |value: &str| {
+        value
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .ok_or_else(|| invalid(&format!("cannot measure loudness (got {:?})", value)))
+    };
+    Ok(LoudnessMeasurement {
+        integrated_lufs: number(&json.input_i)?,
+        true_peak_dbtp: number(&json.input_tp)?,
+        lra: number(&json.input_lra)?,
+        threshold_lufs: number(&json.input_thresh)?,
+        target_offset: number(&json.target_offset)?,
+    })
+}
+
+/// Первый проход: измеряет громкость первой звуковой дорожки `input` (EBU R128) через loudnorm.
+pub fn measure_loudness(
+    ffmpeg: &Path,
+    input: &Path,
+    target_lufs: f64,
+    cancel: &CancelToken,
+) -> io::Result<LoudnessMeasurement> {
+    let args: Vec<String> = vec![
+        "-hide_banner".into(),
+        "-nostats".into(),
+        "-i".into(),
+        input.to_string_lossy().into_owned(),
+        "-map".into(),
+        "0:a:0".into(),
+        "-af".into(),
+        format!("{}:print_format=json", loudnorm_filter(target_lufs)),
+        "-f".into(),
+        "null".into(),
+        "-".into(),
+    ];
+    parse_loudnorm(&run_ffmpeg(ffmpeg, &args, cancel)?)
+}
+
+/// Временный файл рядом с `path` с тем же расширением: "Track.opus" -> "Track.loudnorm.opus".
+fn temp_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(ext) => path.with_file_name(format!("{}.loudnorm.{}", stem, ext.to_string_lossy())),
+        None => path.with_file_name(format!("{}.loudnorm", stem)),
+    }
+}
+
+/// Кодер без потерь: битрейт у него не задаётся.
+fn is_lossless(encoder: &str) -> bool {
+    matches!(encoder, "flac" | "alac") || encoder.starts_with("pcm_")
+}
+
+/// Второй проход: линейный loudnorm по измерениям `measured`, перекодирование кодером `encoder`
+/// с битрейтом (кроме lossless) и частотой исходного файла
+/// (loudnorm иначе передискретизирует в 192 кГц).
+/// Результат пишется во временный файл и заменяет `path` только после успешного завершения.
+fn normalize_loudness(
+    ffmpeg: &Path,
+    path: &Path,
+    encoder: &str,
+    target_lufs: f64,
+    measured: &LoudnessMeasurement,
+    cancel: &CancelToken,
+) -> io::Result<()> {
+    let properties = lofty::read_from_path(path)
+        .map(|file| file.properties().clone())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    let filter = format!(
+        "{}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+        loudnorm_filter(target_lufs),
+        measured.integrated_lufs,
+        measured.true_peak_dbtp,
+        measured.lra,
+        measured.threshold_lufs,
+        measured.target_offset
+    );
+    let tmp = temp_path(path);
+    let mut args: Vec<String> = vec![
+        "-hide_banner".into(),
+        "-nostats".into(),
+        "-y".into(),
+        "-i".into(),
+        path.to_string_lossy().into_owned(),
+        "-map".into(),
+        "0:a:0".into(),
+        "-map_metadata".into(),
+        "0".into(),
+        "-af".into(),
+        filter,
+        "-c:a".into(),
+        encoder.into(),
+    ];
+    if !is_lossless(encoder)
+        && let Some(kbps) = properties.audio_bitrate()
+    {
+        args.extend(["-b:a".into(), format!("{}k", kbps)]);
+    }
+    if let Some(rate) = properties.sample_rate() {
+        args.extend(["-ar".into(), rate.to_string()]);
+    }
+    args.push(tmp.to_string_lossy().into_owned());
+
+    let result = run_ffmpeg(ffmpeg, &args, cancel).and_then(|_| std::fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Этап громкости для готового файла `path`:
+/// - ReplayGain — измеряет громкость и возвращает значения тегов ReplayGain;
+/// - Normalize — измеряет и приводит громкость к `target_lufs` кодером `encoder`.
+///
+/// Отмена `cancel` убивает ffmpeg (ошибка с ErrorKind::Interrupted), исходный файл не меняется.
+pub fn process_loudness(
+    ffmpeg: &Path,
+    path: &Path,
+    mode: LoudnessMode,
+    target_lufs: f64,
+    encoder: Option<&str>,
+    cancel: &CancelToken,
+) -> io::Result<Option<LoudnessReport>> {
+    if mode == LoudnessMode::Off {
+        return Ok(None);
+    }
+    let measured = measure_loudness(ffmpeg, path, target_lufs, cancel)?;
+    let report = match mode {
+        LoudnessMode::Normalize => {
+            let encoder = encoder.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "cannot normalize: unknown encoder for this audio format",
+                )
+            })?;
+            normalize_loudness(ffmpeg, path, encoder, target_lufs, &measured, cancel)?;
+            LoudnessReport {
+                mode,
+                target_lufs: Some(target_lufs),
+                measured,
+                replay_gain: None,
+            }
+        }
+        _ => LoudnessReport {
+            mode,
+            target_lufs: None,
+            replay_gain: Some(measured.replay_gain()),
+            measured,
+        },
+    };
+    Ok(Some(report))
+}
@@ -1,80 +1,250 @@
 use crate::config_manager::Config;
-use actix_cors::Cors;
-use actix_web::{App, HttpResponse, HttpServer, Responder, post, web};
-use anyhow::Result;
-use lazy_static::lazy_static;
+use crate::job_queue::{CancelOutcome, JobId, JobQueue, JobRetention, JobState};
+use crate::pipeline_error::PipelineError;
+use crate::request_parser::parse_download_request;
+use actix_web::http::StatusCode;
+use actix_web::middleware::from_fn;
+use actix_web::{App, HttpResponse, HttpServer, Responder, ResponseError, delete, get, post, web};
+use serde::Deserialize;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use tokio::sync::Mutex;
+use std::time::Duration;
 use tokio::task::JoinHandle;
 
+mod archive_extractor;
+mod auth;
+mod checksum;
 mod collect_soundall;
 mod config_manager;
+mod cover_art;
+mod doctor;
 mod download_manager;
+mod github_releases;
+mod http_client;
+mod job_queue;
+mod listener;
+mod loudness;
 mod path_ext;
+mod pipeline_error;
 mod process_manager;
+mod progress;
+mod request_parser;
 mod structures;
+mod tag_writer;
+mod tool_bundle;
+mod tool_installer;
+mod tool_locator;
+mod tool_manifest;
+mod tool_spec;
+mod ytdlp_options;
 mod zip_extractor;
 
-use crate::download_manager::{
-    check_bin_contains_ffmpeg_and_ytdlp, fetch_ffmpeg_release_async, fetch_ytdlp_release_async,
-    handle_sound_command_async,
-};
+use crate::doctor::{CheckStatus, print_report, run_doctor_async};
+use crate::tool_bundle::{export_bundle, install_bundle_async};
+use crate::tool_installer::{ensure_tools_async, rollback_tool_async, update_tools_async};
+use crate::tool_locator::resolve;
+use crate::tool_manifest::ToolManifest;
+use crate::tool_spec::{ToolSpec, find_spec, tool_specs};
+
+/// Меньше минуты завершённая задача не хранится, даже если finished_job_ttl_secs меньше
+const MIN_FINISHED_JOB_TTL_SECS: u64 = 60;
+
+#[derive(Deserialize)]
+struct DownloadQuery {
+    /// Дождаться завершения задачи и вернуть её результат вместо идентификатора
+    #[serde(default)]
+    wait: bool,
+}
 
 #[post("/download")]
-async fn download(body: String) -> impl Responder {
+async fn download(
+    query: web::Query<DownloadQuery>,
+    body: String,
+) -> Result<HttpResponse, PipelineError> {
     println!("HTTP received: {}", body);
 
-    let config = Config::get();
+    let request = parse_download_request(&body)?;
+    let queue = JobQueue::get();
+    let id = match queue.enqueue(request) {
+        Ok(id) => id,
+        Err(e) => {
+            return Ok(HttpResponse::ServiceUnavailable()
+                .json(serde_json::json!({ "error": e.to_string() })));
+        }
+    };
+    if !query.wait {
+        return Ok(
+            HttpResponse::Accepted().json(serde_json::json!({ "id": id, "state": "queued" }))
+        );
+    }
+
+    let info = queue
+        .wait(id)
+        .await
+        .ok_or_else(|| PipelineError::Io(io::Error::other("job disappeared from the queue")))?;
+    Ok(match (&info.error, info.state) {
+        (Some(error), _) => HttpResponse::build(
+            StatusCode::from_u16(error.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+        )
+        .json(error),
+        (None, JobState::Cancelled) => PipelineError::Cancelled.error_response(),
+        (None, _) => HttpResponse::Ok().json(info),
+    })
+}
+
+#[get("/jobs")]
+async fn list_jobs() -> impl Responder {
+    HttpResponse::Ok().json(JobQueue::get().list())
+}
+
+#[get("/jobs/{id}")]
+async fn get_job(path: web::Path<JobId>) -> impl Responder {
+    match JobQueue::get().info(path.into_inner()) {
+        Some(info) => HttpResponse::Ok().json(info),
+        None => HttpResponse::NotFound().json(serde_json::json!({ "error": "job not found" })),
+    }
+}
 
-    handle_sound_command_async(body.as_str(), &config.unwrap().download_path).await;
+#[delete("/jobs/{id}")]
+async fn cancel_job(path: web::Path<JobId>) -> impl Responder {
+    match JobQueue::get().cancel(path.into_inner()) {
+        CancelOutcome::Cancelled(info) => HttpResponse::Ok().json(info),
+        CancelOutcome::AlreadyFinished(info) => HttpResponse::Conflict().json(info),
+        CancelOutcome::NotFound => {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "job not found" }))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct EventsQuery {
+    job: Option<JobId>,
+}
+
+/// Поток событий прогресса (Server-Sent Events); `?job=<id>` оставляет события одной задачи.
+#[get("/events")]
+async fn events(query: web::Query<EventsQuery>) -> impl Responder {
     HttpResponse::Ok()
-        .content_type("text/plain; charset=utf-8")
-        .body(format!("Received {} bytes", body.len()))
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(progress::sse_stream(query.job))
 }
 
-async fn init_console() {
-    println!("By UnderKo");
-    println!("https://github.com/underkogit/ytdlp-vk");
-    println!("Using: ytdlp, ffmpeg");
+#[derive(Deserialize)]
+struct ToolQuery {
+    /// Имя инструмента ("yt-dlp", "ffmpeg", "curl" на Windows или из extra_tools); для обновления без него — все инструменты
+    tool: Option<String>,
+}
 
-    if (!check_bin_contains_ffmpeg_and_ytdlp(Path::new("bin_"))) {
-        if let Err(e) = fetch_ffmpeg_release_async(
-            "ffmpeg-master-latest-win64-lgpl-shared.zip",
-            "https://api.github.com/repos/BtbN/FFmpeg-Builds/releases",
-        )
-        .await
-        {
-            eprintln!("FFmpeg download failed: {}", e);
+/// Инструменты из запроса: один по имени или все; ошибка 404, если имя неизвестно.
+fn tools_from_query(query: &ToolQuery) -> Result<Vec<ToolSpec>, HttpResponse> {
+    match &query.tool {
+        None => Ok(tool_specs()),
+        Some(name) => find_spec(name).map(|spec| vec![spec]).ok_or_else(|| {
+            HttpResponse::NotFound().json(serde_json::json!({ "error": "unknown tool" }))
+        }),
+    }
+}
+
+/// Манифест tools.json и пути, по которым сейчас находятся инструменты.
+#[get("/tools")]
+async fn list_tools() -> impl Responder {
+    let resolved: serde_json::Map<String, serde_json::Value> = tool_specs()
+        .iter()
+        .map(|spec| (spec.name.clone(), serde_json::json!(resolve(&spec.name))))
+        .collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "manifest": ToolManifest::load().unwrap_or_default(),
+        "resolved": resolved,
+    }))
+}
+
+/// Проверяет релизы и обновляет инструменты; `?tool=` ограничивает одним инструментом.
+#[post("/tools/update")]
+async fn update_tools(query: web::Query<ToolQuery>) -> impl Responder {
+    match tools_from_query(&query) {
+        Ok(tools) => HttpResponse::Ok().json(update_tools_async(&tools).await),
+        Err(response) => response,
+    }
+}
+
+/// Откатывает инструмент `?tool=` к предыдущей установленной версии.
+#[post("/tools/rollback")]
+async fn rollback_tool(query: web::Query<ToolQuery>) -> impl Responder {
+    let spec = match tools_from_query(&query) {
+        Ok(mut specs) if query.tool.is_some() => specs.remove(0),
+        Ok(_) => {
+            return HttpResponse::BadRequest()
+                .json(serde_json::json!({ "error": "tool parameter is required" }));
         }
+        Err(response) => return response,
+    };
+    match rollback_tool_async(&spec.name).await {
+        Ok(record) => HttpResponse::Ok().json(record),
+        Err(e) => HttpResponse::Conflict().json(serde_json::json!({ "error": format!("{:#}", e) })),
+    }
+}
 
-        if let Err(e) = fetch_ytdlp_release_async(
-            "yt-dlp.exe",
-            "https://api.github.com/repos/yt-dlp/yt-dlp/releases",
-        )
-        .await
-        {
-            eprintln!("yt-dlp download failed: {}", e);
+/// Диагностика окружения: конфиг, каталог загрузок, инструменты, кодеки, порт.
+/// Требует bearer-токен (см. auth::require_token).
+#[get("/doctor")]
+async fn doctor_report() -> impl Responder {
+    HttpResponse::Ok().json(run_doctor_async().await)
+}
+
+/// Команды командной строки (`ytdlp-vk <команда> ...`), выполняются вместо запуска сервера.
+async fn run_command(command: &str, args: &[String]) -> anyhow::Result<()> {
+    match (command, args) {
+        ("doctor", []) => {
+            let report = run_doctor_async().await;
+            print_report(&report);
+            if report.status == CheckStatus::Fail {
+                anyhow::bail!("some checks failed");
+            }
+        }
+        ("install-tools", [bundle]) => {
+            let installed = install_bundle_async(Path::new(bundle), false).await?;
+            println!("installed from bundle: {}", installed.join(", "));
+        }
+        ("export-tools", [dest]) => {
+            let dest = PathBuf::from(dest);
+            let exported = tokio::task::spawn_blocking(move || export_bundle(&dest)).await??;
+            println!("exported: {}", exported.join(", "));
         }
+        _ => anyhow::bail!(
+            "unknown command\nusage: ytdlp-vk [doctor | install-tools <bundle dir|archive> | export-tools <dir|file.tar.gz>]"
+        ),
     }
+    Ok(())
 }
 
-fn cors_middleware() -> Cors {
-    Cors::default()
-        .allowed_methods(vec!["GET", "POST", "DELETE", "PUT"])
-        .allowed_headers(vec![
-            http::header::AUTHORIZATION,
-            http::header::ACCEPT,
-            http::header::CONTENT_TYPE,
-        ])
-        .max_age(3600)
+async fn init_console() {
+    println!("By UnderKo");
+    println!("https://github.com/underkogit/ytdlp-vk");
+    println!("Using: ytdlp, ffmpeg");
+
+    ensure_tools_async().await;
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    init_console().await;
-    Config::init()?;
+    let init = Config::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some((command, args)) = args.split_first() {
+        // doctor сам сообщает об ошибке в config.json
+        if command != "doctor" {
+            init?;
+        }
+        return Ok(run_command(command, args).await?);
+    }
+    init?;
     let config = Config::get_unwrap();
+    init_console().await;
+    let token = auth::load_or_create_token()?;
+    println!(
+        "API token (send as 'Authorization: Bearer <token>'): {}",
+        token
+    );
 
     if config.validate_path() {
         println!("Folder exists. Continuing execution...");
@@ -82,16 +252,65 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("Warning: folder {} does not exist!", config.download_path);
     }
 
+    JobQueue::start(
+        config.max_concurrent_jobs,
+        &config.download_path,
+        JobRetention {
+            // GET /jobs/{id} должен успеть вернуть результат только что завершённой задачи
+            max_finished: config.finished_jobs_limit.max(1),
+            max_age: Duration::from_secs(
+                config.finished_job_ttl_secs.max(MIN_FINISHED_JOB_TTL_SECS),
+            ),
+        },
+    )?;
+
     // Создаём и запускаем сервер
-    let server = HttpServer::new(|| {
+    let mut server = HttpServer::new(move || {
         App::new()
-            .wrap(cors_middleware())
-            // permissive удобно для разработки; при проде лучше настроить конкретные origin
-            .wrap(Cors::permissive())
+            .wrap(from_fn(auth::require_token))
+            .wrap(auth::cors_middleware(config))
             .service(download)
-    })
-    .bind(("127.0.0.1", 1488))?
-    .run();
+            .service(list_jobs)
+            .service(get_job)
+            .service(cancel_job)
+            .service(events)
+            .service(list_tools)
+            .service(update_tools)
+            .service(rollback_tool)
+            .service(doctor_report)
+    });
+
+    let mut endpoint = listener::Endpoint {
+        pid: std::process::id(),
+        ..Default::default()
+    };
+    if config.listen_tcp {
+        let tcp = listener::bind_tcp_with_fallback(&config.host, config.port)?;
+        let addr = tcp.local_addr()?;
+        server = server.listen(tcp)?;
+        println!("Listening on http://{}", addr);
+        endpoint.url = Some(format!("http://{}", addr));
+        endpoint.host = Some(addr.ip().to_string());
+        endpoint.port = Some(addr.port());
+    }
+    if let Some(socket) = &config.unix_socket {
+        #[cfg(unix)]
+        {
+            server = server.listen_uds(listener::bind_unix_socket(Path::new(socket))?)?;
+            println!("Listening on unix socket {}", socket);
+            endpoint.unix_socket = Some(socket.clone());
+        }
+        #[cfg(not(unix))]
+        println!(
+            "Warning: unix_socket {} is ignored on this platform",
+            socket
+        );
+    }
+    if endpoint.url.is_none() && endpoint.unix_socket.is_none() {
+        return Err("No listener configured: enable listen_tcp or set unix_socket".into());
+    }
+    listener::write_discovery_file(&endpoint)?;
+    let server = server.run();
 
     // Получаем handle и запускаем сервер в фоне
     let handle = server.handle();
@@ -105,12 +324,88 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         io::stdin().read_line(&mut raw_input)?;
         let raw_input = raw_input.trim_end();
 
-        handle_sound_command_async(raw_input, &config.download_path).await;
-
         if raw_input.starts_with(":help") || raw_input.starts_with(":?") {
             println!(
-                "image:\"url\"; yt-dlp -x --audio-format mp3 --embed-thumbnail --add-metadata -o \"PATH/Artist - Title.mp3\" \"URL\""
+                "{{\"version\": 1, \"image_url\": \"URL\", \"media_url\": \"URL\", \"artist\": \"Artist\", \"title\": \"Title\"}}"
             );
+            println!(
+                "image:\"url\"; yt-dlp -x --audio-format mp3 --embed-thumbnail --add-metadata -o \"PATH/Artist - Title.mp3\" \"URL\"; json-data:{{...}}"
+            );
+            println!(
+                ":update [tool]  - check releases and update tools (yt-dlp, ffmpeg, curl on Windows, extra_tools)"
+            );
+            println!(":rollback <tool> - restore the previous tool version");
+            println!(":doctor - check config, folders, tools, codecs and port");
+            println!(":install-tools <bundle> - install tools from an offline bundle");
+            println!(":export-tools <dir|file.tar.gz> - save installed tools as an offline bundle");
+            continue;
+        }
+
+        if let Some(args) = raw_input.strip_prefix(":update") {
+            let specs = match args.trim() {
+                "" => tool_specs(),
+                name => match find_spec(name) {
+                    Some(spec) => vec![spec],
+                    None => {
+                        eprintln!("Error: unknown tool {}", name);
+                        continue;
+                    }
+                },
+            };
+            for outcome in update_tools_async(&specs).await {
+                println!(
+                    "{}: {:?} (installed: {}, available: {}){}",
+                    outcome.tool,
+                    outcome.status,
+                    outcome.installed.as_deref().unwrap_or("-"),
+                    outcome.available.as_deref().unwrap_or("-"),
+                    outcome
+                        .message
+                        .map(|m| format!(" {}", m))
+                        .unwrap_or_default()
+                );
+            }
+            continue;
+        }
+
+        if let Some(name) = raw_input.strip_prefix(":rollback") {
+            match find_spec(name.trim()) {
+                Some(spec) => {
+                    if let Err(e) = rollback_tool_async(&spec.name).await {
+                        eprintln!("Error: {:#}", e);
+                    }
+                }
+                None => eprintln!("Error: usage :rollback <tool>"),
+            }
+            continue;
+        }
+
+        if raw_input == ":doctor" {
+            print_report(&run_doctor_async().await);
+            continue;
+        }
+
+        if let Some(bundle) = raw_input.strip_prefix(":install-tools") {
+            let args: Vec<String> = Some(bundle.trim())
+                .filter(|arg| !arg.is_empty())
+                .map(String::from)
+                .into_iter()
+                .collect();
+            if let Err(e) = run_command("install-tools", &args).await {
+                eprintln!("Error: {:#}", e);
+            }
+            continue;
+        }
+
+        if let Some(dest) = raw_input.strip_prefix(":export-tools") {
+            let args: Vec<String> = Some(dest.trim())
+                .filter(|arg| !arg.is_empty())
+                .map(String::from)
+                .into_iter()
+                .collect();
+            if let Err(e) = run_command("export-tools", &args).await {
+                eprintln!("Error: {:#}", e);
+            }
             continue;
         }
 
@@ -118,8 +413,24 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             // Останавливаем сервер и ждём задачи
             let _ = handle.stop(true).await;
             let _ = server_task.await;
+            listener::remove_discovery_file();
             break;
         }
+
+        if !raw_input.is_empty() {
+            match parse_download_request(raw_input) {
+                Ok(request) => {
+                    if let Err(e) = JobQueue::get().enqueue(request) {
+                        eprintln!("Error: {:#}", e);
+                    }
+                }
+                Err(fields) => {
+                    for field in fields {
+                        eprintln!("Error: {}: {}", field.field, field.message);
+                    }
+                }
+            }
+        }
     }
 
     Ok(())
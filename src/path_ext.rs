@@ -1,73 +1,163 @@
 use std::io;
-use std::path::{Path, PathBuf};
-use tokio::fs;
+use std::path::{Component, Path, PathBuf};
 
-/// Собирает путь из папки и имени файла.
-///
-/// # Аргументы
-/// - `folder`: путь к папке (абсолютный или относительный).
-/// - `filename`: имя файла или относительный путь внутри `folder`.
-///
-/// # Возвращает
-/// `PathBuf` — результат эквивалентный `Path::new(folder).join(filename)`.
-pub fn join_path(folder: &str, filename: &str) -> PathBuf {
-    Path::new(folder).join(filename)
+/// Похож ли `path` на путь Windows с диском или UNC ("C:\\x", "C:x", "\\\\server\\share").
+/// На Unix такие строки — обычные имена файлов, но запрос с ними всё равно подозрителен.
+fn has_windows_prefix(path: &Path) -> bool {
+    let s = path.to_string_lossy();
+    let bytes = s.as_bytes();
+    let drive = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    drive || s.starts_with("\\\\") || s.starts_with("//")
 }
 
-/// Извлекает путь, указанный после ключа `-o` в строке аргументов.
-///
-/// Поддерживает кавычки и экранирование через `shell_words`, а также развёртывание `~`
-/// через `shellexpand`.
-///
-/// # Аргументы
-/// - `arg_line`: строка аргументов (например, командная строка).
-///
-/// # Возвращает
-/// `Option<PathBuf>`:
-/// - `Some(PathBuf)` с развернутым путем, если найден ключ `-o` с последующим аргументом;
-/// - `None` если парсинг не удался или `-o` не найден.
-pub fn extract_output_path(arg_line: &str) -> Option<PathBuf> {
-    shell_words::split(arg_line)
-        .ok()?
-        .windows(2)
-        .find(|w| w[0] == "-o")
-        .map(|w| shellexpand::tilde(&w[1]).into_owned().into())
+/// Самый глубокий существующий предок `path` в канонической форме.
+fn canonical_existing_ancestor(path: &Path) -> io::Result<PathBuf> {
+    let mut ancestor = path;
+    loop {
+        match std::fs::canonicalize(ancestor) {
+            Ok(canonical) => return Ok(canonical),
+            // битая символическая ссылка: куда она ведёт, проверить нельзя
+            Err(e)
+                if e.kind() == io::ErrorKind::NotFound && ancestor.symlink_metadata().is_ok() =>
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    format!("'{}' is a dangling symbolic link", ancestor.display()),
+                ));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                ancestor = ancestor.parent().ok_or(e)?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
 }
 
-/// Удаляет файл по `original_path`, если он существует, затем переименовывает файл,
-/// указанной строкой `out_path_o_str`, убирая из имени суффикс `.mp3_t` (только в последней части пути).
+/// Строит путь `relative` внутри каталога `base` и гарантирует, что он не выходит за его пределы.
 ///
-/// # Аргументы
-/// - `original_path`: путь к возможному файлу, который надо удалить перед переименованием.
-/// - `out_path_o_str`: строка пути к текущему файлу, который нужно переименовать.
+/// - `relative` должен быть относительным и не содержать `..`, корня или префикса диска;
+/// - `base` канонизируется (должен существовать);
+/// - до создания каталогов проверяется канонический самый глубокий существующий предок,
+///   поэтому символическая ссылка наружу не даёт создать ничего вне `base`;
+/// - после создания родительских каталогов канонический родитель (и сам файл, если он уже есть)
+///   проверяется ещё раз.
+///
+/// # Возвращает
+/// Абсолютный путь внутри канонического `base`.
 ///
 /// # Ошибки
-/// Возвращает `io::Error` в случае проблем с удалением/переименованием или если
-/// `out_path_o_str` не содержит корректного имени файла.
-pub async fn remove_and_rename(original_path: &Path, out_path_o_str: &str) -> io::Result<()> {
-    // удалить original_path, если существует
-    if original_path.exists() {
-        fs::remove_file(original_path).await?;
+/// `io::ErrorKind::PermissionDenied` при попытке выйти за пределы `base`,
+/// либо ошибки файловой системы при канонизации и создании каталогов.
+pub fn confine_to_base(base: &Path, relative: &Path) -> io::Result<PathBuf> {
+    let escapes = |path: &Path| {
+        io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "path '{}' escapes the folder '{}'",
+                path.display(),
+                base.display()
+            ),
+        )
+    };
+
+    if has_windows_prefix(relative)
+        || relative
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(escapes(relative));
+    }
+    let file_name = relative.file_name().ok_or_else(|| escapes(relative))?;
+
+    let base = std::fs::canonicalize(base)?;
+    let joined = base.join(relative);
+    let parent = joined.parent().ok_or_else(|| escapes(relative))?;
+    if !canonical_existing_ancestor(parent)?.starts_with(&base) {
+        return Err(escapes(relative));
     }
+    std::fs::create_dir_all(parent)?;
+
+    let parent = std::fs::canonicalize(parent)?;
+    if !parent.starts_with(&base) {
+        return Err(escapes(relative));
+    }
+    let confined = parent.join(file_name);
+    if confined.symlink_metadata().is_ok() && !std::fs::canonicalize(&confined)?.starts_with(&base)
+    {
+        return Err(escapes(relative));
+    }
+    Ok(confined)
+}
 
-    // сформировать новый путь, заменив ".mp3_t" в имени файла (только последний сегмент)
-    let out_path = Path::new(out_path_o_str);
-    let parent = out_path.parent().unwrap_or_else(|| Path::new(""));
-    let file_name = out_path
-        .file_name()
-        .and_then(|s| s.to_str())
-        .ok_or_else(|| {
-            io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "invalid out_path_o_str filename",
-            )
-        })?;
-
-    let new_file_name = file_name.replace(".mp3_t", "");
-    let new_path = parent.join(new_file_name);
-
-    // переименовать
-    fs::rename(out_path, &new_path).await?;
-
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_escapes(base: &Path, relative: &str) {
+        let err = confine_to_base(base, Path::new(relative)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied, "{}", relative);
+    }
+
+    #[test]
+    fn confines_nested_path_and_creates_parents() {
+        let base = tempfile::tempdir().unwrap();
+        let path = confine_to_base(base.path(), Path::new("Artist/./Track.mp3")).unwrap();
+        let canonical = std::fs::canonicalize(base.path()).unwrap();
+        assert_eq!(path, canonical.join("Artist").join("Track.mp3"));
+        assert!(canonical.join("Artist").is_dir());
+    }
+
+    #[test]
+    fn rejects_parent_dir_components() {
+        let base = tempfile::tempdir().unwrap();
+        assert_escapes(base.path(), "../Track.mp3");
+        assert_escapes(base.path(), "Artist/../../Track.mp3");
+        assert_escapes(base.path(), "..");
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        let base = tempfile::tempdir().unwrap();
+        assert_escapes(base.path(), "/etc/Track.mp3");
+        assert_escapes(base.path(), "/");
+    }
+
+    #[test]
+    fn rejects_windows_drive_and_unc_prefixes() {
+        let base = tempfile::tempdir().unwrap();
+        assert_escapes(base.path(), "C:\\Music\\Track.mp3");
+        assert_escapes(base.path(), "C:Track.mp3");
+        assert_escapes(base.path(), "c:/Music/Track.mp3");
+        assert_escapes(base.path(), "\\\\server\\share\\Track.mp3");
+        assert_escapes(base.path(), "//server/share/Track.mp3");
+        assert!(std::fs::read_dir(base.path()).unwrap().next().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinked_directory_escape_before_creating_anything() {
+        let base = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(outside.path(), base.path().join("link")).unwrap();
+
+        assert_escapes(base.path(), "link/Track.mp3");
+        assert_escapes(base.path(), "link/new/deeper/Track.mp3");
+        assert!(!outside.path().join("new").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinked_file_and_dangling_link() {
+        let base = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret"), b"x").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret"), base.path().join("Track.mp3"))
+            .unwrap();
+        std::os::unix::fs::symlink(outside.path().join("missing"), base.path().join("dangling"))
+            .unwrap();
+
+        assert_escapes(base.path(), "Track.mp3");
+        assert_escapes(base.path(), "dangling/new/Track.mp3");
+        assert!(!outside.path().join("missing").exists());
+    }
 }
@@ -0,0 +1,154 @@
+use crate::structures::download_request::FieldError;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+use std::fmt;
+use std::io;
+
+/// Ошибка конвейера скачивания: от разбора запроса до тегирования файла.
+#[derive(Debug)]
+pub enum PipelineError {
+    /// Некорректный запрос; содержит ошибки по полям
+    Parse(Vec<FieldError>),
+    /// yt-dlp не запустился, завершился с ошибкой или не создал файл
+    YtDlp(String),
+    /// Не удалось скачать обложку
+    Cover(String),
+    /// ffmpeg не найден или не смог обработать громкость
+    Ffmpeg(String),
+    /// Не удалось записать теги/обложку в файл
+    Tagging(String),
+    /// Путь вывода, обложки или data.json выходит за пределы download_path
+    Path(String),
+    /// Ошибка ввода-вывода при работе с файлами
+    Io(io::Error),
+    /// Задача отменена пользователем
+    Cancelled,
+}
+
+/// JSON-тело ошибки, которое отдаётся клиенту и сохраняется в задаче.
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorBody {
+    pub error: &'static str,
+    pub message: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldError>,
+}
+
+impl PipelineError {
+    /// Машиночитаемый код ошибки для поля "error" в ответе.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PipelineError::Parse(_) => "invalid_request",
+            PipelineError::YtDlp(_) => "ytdlp_failed",
+            PipelineError::Cover(_) => "cover_failed",
+            PipelineError::Ffmpeg(_) => "ffmpeg_failed",
+            PipelineError::Tagging(_) => "tagging_failed",
+            PipelineError::Path(_) => "path_violation",
+            PipelineError::Io(_) => "io_error",
+            PipelineError::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn to_body(&self) -> ErrorBody {
+        ErrorBody {
+            error: self.kind(),
+            message: self.to_string(),
+            status: self.status_code().as_u16(),
+            fields: match self {
+                PipelineError::Parse(fields) => fields.clone(),
+                _ => Vec::new(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Parse(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|e| format!("{}: {}", e.field, e.message))
+                    .collect();
+                write!(f, "invalid request: {}", fields.join("; "))
+            }
+            PipelineError::YtDlp(msg) => write!(f, "yt-dlp failed: {}", msg),
+            PipelineError::Cover(msg) => write!(f, "cover download failed: {}", msg),
+            PipelineError::Ffmpeg(msg) => write!(f, "ffmpeg failed: {}", msg),
+            PipelineError::Tagging(msg) => write!(f, "tagging failed: {}", msg),
+            PipelineError::Path(msg) => write!(f, "path rejected: {}", msg),
+            PipelineError::Io(e) => write!(f, "I/O error: {}", e),
+            PipelineError::Cancelled => write!(f, "job cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PipelineError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PipelineError {
+    fn from(e: io::Error) -> Self {
+        PipelineError::Io(e)
+    }
+}
+
+impl From<Vec<FieldError>> for PipelineError {
+    fn from(fields: Vec<FieldError>) -> Self {
+        PipelineError::Parse(fields)
+    }
+}
+
+impl ResponseError for PipelineError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            PipelineError::Parse(_) => StatusCode::BAD_REQUEST,
+            PipelineError::YtDlp(_) => StatusCode::BAD_GATEWAY,
+            PipelineError::Cover(_) => StatusCode::FAILED_DEPENDENCY,
+            PipelineError::Ffmpeg(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            // файл уже скачан и перекодирован сервером — сбой записи тегов на его стороне;
+            // от Io отличается полем "error" (tagging_failed)
+            PipelineError::Tagging(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            PipelineError::Path(_) => StatusCode::FORBIDDEN,
+            PipelineError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            PipelineError::Cancelled => StatusCode::CONFLICT,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self.to_body())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_code_per_variant() {
+        let cases = [
+            (
+                PipelineError::Parse(vec![FieldError::new("media_url", "required")]),
+                400,
+            ),
+            (PipelineError::YtDlp("exit 1".into()), 502),
+            (PipelineError::Cover("404".into()), 424),
+            (PipelineError::Ffmpeg("exit 1".into()), 422),
+            (PipelineError::Tagging("bad frame".into()), 500),
+            (PipelineError::Path("..".into()), 403),
+            (PipelineError::Io(io::Error::other("disk full")), 500),
+            (PipelineError::Cancelled, 409),
+        ];
+        for (error, status) in cases {
+            assert_eq!(error.status_code().as_u16(), status, "{}", error.kind());
+            assert_eq!(error.to_body().status, status);
+        }
+    }
+}
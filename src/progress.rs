@@ -0,0 +1,96 @@
+use crate::job_queue::{JobId, JobState};
+use crate::pipeline_error::ErrorBody;
+use actix_web::web::Bytes;
+use futures_util::Stream;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use std::convert::Infallible;
+use tokio::sync::broadcast;
+
+/// Событие прогресса задачи, рассылаемое подписчикам GET /events.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ProgressEvent {
+    /// Строка прогресса yt-dlp, разобранная на поля.
+    Progress {
+        job_id: JobId,
+        percent: f32,
+        total: Option<String>,
+        speed: Option<String>,
+        eta: Option<String>,
+    },
+    /// Переход задачи на новый этап (обложка, теги, индексация, завершение и т.д.).
+    Phase {
+        job_id: JobId,
+        state: JobState,
+        error: Option<ErrorBody>,
+    },
+}
+
+impl ProgressEvent {
+    pub fn job_id(&self) -> JobId {
+        match self {
+            ProgressEvent::Progress { job_id, .. } | ProgressEvent::Phase { job_id, .. } => *job_id,
+        }
+    }
+
+    /// Форматирует событие как кадр Server-Sent Events.
+    fn to_sse(&self) -> String {
+        let name = match self {
+            ProgressEvent::Progress { .. } => "progress",
+            ProgressEvent::Phase { .. } => "phase",
+        };
+        let data = serde_json::to_string(self).unwrap_or_default();
+        format!("event: {}\ndata: {}\n\n", name, data)
+    }
+}
+
+/// Глобальный канал событий; отстающие подписчики теряют старые события, а не тормозят задачи.
+static EVENTS: Lazy<broadcast::Sender<ProgressEvent>> = Lazy::new(|| broadcast::channel(256).0);
+
+/// Регулярное выражение для строк вида
+/// "[download]  42.0% of ~  3.45MiB at  512.00KiB/s ETA 00:06" и "[download] 100% of 3.45MiB in 00:00:02 at 1.5MiB/s".
+static YTDLP_PROGRESS: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^\[download\]\s+(?P<percent>[\d.]+)%(?:\s+of\s+~?\s*(?P<total>\S+))?(?:\s+in\s+\S+)?(?:\s+at\s+(?P<speed>\S+))?(?:\s+ETA\s+(?P<eta>\S+))?",
+    )
+    .unwrap()
+});
+
+/// Рассылает событие всем подписчикам. Отсутствие подписчиков ошибкой не считается.
+pub fn publish(event: ProgressEvent) {
+    let _ = EVENTS.send(event);
+}
+
+/// Разбирает строку вывода yt-dlp и, если это строка прогресса, возвращает событие для задачи `job_id`.
+pub fn parse_ytdlp_progress(job_id: JobId, line: &str) -> Option<ProgressEvent> {
+    let caps = YTDLP_PROGRESS.captures(line.trim())?;
+    let field = |name: &str| {
+        caps.name(name)
+            .map(|m| m.as_str().to_string())
+            .filter(|s| s != "Unknown" && s != "N/A")
+    };
+    Some(ProgressEvent::Progress {
+        job_id,
+        percent: caps["percent"].parse().ok()?,
+        total: field("total"),
+        speed: field("speed"),
+        eta: field("eta"),
+    })
+}
+
+/// Возвращает поток кадров SSE для подписчика; при `job` = Some — только события этой задачи.
+pub fn sse_stream(job: Option<JobId>) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    futures_util::stream::unfold(EVENTS.subscribe(), move |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) if job.is_none_or(|id| event.job_id() == id) => {
+                    return Some((Ok(Bytes::from(event.to_sse())), rx));
+                }
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+}
@@ -0,0 +1,150 @@
+use crate::structures::download_request::{
+    DOWNLOAD_REQUEST_VERSION, DownloadOptions, DownloadRequest, FieldError,
+};
+use crate::ytdlp_options::{options_from_legacy_args, validate_options};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+/// Старый формат команды: image:"url"; yt-dlp: <аргументы>; json-data:{...}
+/// Сегменты ищутся по ключевым словам, поэтому ';' внутри URL или JSON не ломает разбор.
+static LEGACY_COMMAND: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?s)^image:?\s*(?P<image>.*?)\s*;\s*yt-dlp:?\s*(?P<ytdlp>.*?)\s*;\s*json-data:?\s*(?P<json>.*)$",
+    )
+    .unwrap()
+});
+
+static MISSING_FIELD: Lazy<Regex> = Lazy::new(|| Regex::new(r"^missing field `(\w+)`").unwrap());
+
+/// Разбирает тело запроса /download.
+/// - тело, начинающееся с '{', разбирается как JSON-схема DownloadRequest;
+/// - иначе — как команда старого формата (для старых сборок расширения);
+/// - результат проверяется validate().
+///
+/// Возвращает список ошибок по полям, если запрос некорректен.
+pub fn parse_download_request(body: &str) -> Result<DownloadRequest, Vec<FieldError>> {
+    let body = body.trim();
+    let request = if body.starts_with('{') {
+        parse_json_request(body)?
+    } else {
+        parse_legacy_request(body)?
+    };
+    validate(&request)?;
+    Ok(request)
+}
+
+/// Десериализует JSON-схему, сохраняя путь к полю, в котором произошла ошибка.
+fn parse_json_request(body: &str) -> Result<DownloadRequest, Vec<FieldError>> {
+    let de = &mut serde_json::Deserializer::from_str(body);
+    serde_path_to_error::deserialize(de).map_err(|e| {
+        let message = e.inner().to_string();
+        let path = e.path().to_string();
+        let field = match MISSING_FIELD.captures(&message) {
+            Some(caps) if path == "." => caps[1].to_string(),
+            Some(caps) => format!("{}.{}", path, &caps[1]),
+            None if path == "." => "body".to_string(),
+            None => path,
+        };
+        vec![FieldError::new(field, message)]
+    })
+}
+
+/// Преобразует команду старого формата в DownloadRequest:
+/// - URL обложки берётся из сегмента image (кавычки снимаются);
+/// - аргументы yt-dlp переводятся в DownloadOptions через options_from_legacy_args,
+///   опции вне разрешённого списка отклоняются;
+/// - json-data должен быть JSON-объектом, safeArtist/safeTitle становятся artist/title.
+fn parse_legacy_request(body: &str) -> Result<DownloadRequest, Vec<FieldError>> {
+    let caps = LEGACY_COMMAND.captures(body).ok_or_else(|| {
+        vec![FieldError::new(
+            "body",
+            "expected a JSON object or a legacy command: image:\"url\"; yt-dlp: ...; json-data:{...}",
+        )]
+    })?;
+
+    let mut errors = Vec::new();
+    let mut options = DownloadOptions::default();
+    let mut media_url = String::new();
+
+    match shell_words::split(&caps["ytdlp"]).map(options_from_legacy_args) {
+        Ok(Ok((legacy_options, url))) => {
+            options = legacy_options;
+            media_url = url.unwrap_or_default();
+        }
+        Ok(Err(legacy_errors)) => errors.extend(legacy_errors),
+        Err(e) => errors.push(FieldError::new("yt-dlp", e.to_string())),
+    }
+
+    let metadata = match serde_json::from_str::<Value>(&caps["json"]) {
+        Ok(Value::Object(map)) => map,
+        Ok(_) => {
+            errors.push(FieldError::new("json-data", "must be a JSON object"));
+            Default::default()
+        }
+        Err(e) => {
+            errors.push(FieldError::new("json-data", e.to_string()));
+            Default::default()
+        }
+    };
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let text = |key: &str| {
+        metadata
+            .get(key)
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+    Ok(DownloadRequest {
+        version: DOWNLOAD_REQUEST_VERSION,
+        image_url: caps["image"].trim().trim_matches('"').to_string(),
+        media_url,
+        artist: text("safeArtist"),
+        title: text("safeTitle"),
+        metadata,
+        options,
+    })
+}
+
+/// Проверяет значения полей, собирая все ошибки, а не только первую.
+fn validate(request: &DownloadRequest) -> Result<(), Vec<FieldError>> {
+    let mut errors = Vec::new();
+    let is_http = |url: &str| url.starts_with("http://") || url.starts_with("https://");
+
+    if request.version != DOWNLOAD_REQUEST_VERSION {
+        errors.push(FieldError::new(
+            "version",
+            format!(
+                "unsupported version {}, expected {}",
+                request.version, DOWNLOAD_REQUEST_VERSION
+            ),
+        ));
+    }
+    if !is_http(&request.image_url) {
+        errors.push(FieldError::new("image_url", "must be an http(s) URL"));
+    }
+    if !is_http(&request.media_url) {
+        errors.push(FieldError::new("media_url", "must be an http(s) URL"));
+    }
+    validate_options(&request.options, &mut errors);
+    if request.options.output.is_none()
+        && request.artist.trim().is_empty()
+        && request.title.trim().is_empty()
+    {
+        errors.push(FieldError::new(
+            "title",
+            "artist or title is required when options.output is not set",
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
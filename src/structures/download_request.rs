@@ -0,0 +1,140 @@
+use crate::config_manager::Config;
+use crate::loudness::LoudnessMode;
+use crate::structures::track_metadata::TrackMetadata;
+use crate::ytdlp_options::audio_extension;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+/// Текущая версия схемы JSON-запроса POST /download
+pub const DOWNLOAD_REQUEST_VERSION: u32 = 1;
+
+/// Запрос на скачивание трека (схема версии 1).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DownloadRequest {
+    pub version: u32,
+    pub image_url: String,
+    pub media_url: String,
+    pub artist: String,
+    pub title: String,
+    /// Дополнительные данные трека, сохраняются в data.json рядом с файлом
+    #[serde(default)]
+    pub metadata: Map<String, Value>,
+    #[serde(default)]
+    pub options: DownloadOptions,
+}
+
+impl DownloadRequest {
+    /// Путь к итоговому файлу относительно download_path:
+    /// options.output, либо "Artist - Title/Artist - Title.<расширение audio_format>".
+    pub fn output_file(&self) -> String {
+        if let Some(output) = &self.options.output {
+            return output.clone();
+        }
+        let name: String = format!("{} - {}", self.artist.trim(), self.title.trim())
+            .chars()
+            .map(|c| match c {
+                '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+                c if c.is_control() => '_',
+                c => c,
+            })
+            .collect();
+        let name = name.trim_matches(|c: char| c == '.' || c.is_whitespace());
+        format!(
+            "{0}/{0}.{1}",
+            name,
+            audio_extension(&self.options.audio_format)
+        )
+    }
+
+    /// Метаданные трека для тегов: artist/title запроса и поля из metadata (см. TrackMetadata).
+    pub fn track_metadata(&self) -> TrackMetadata {
+        TrackMetadata::from_json(&self.metadata, &self.artist, &self.title)
+    }
+}
+
+/// Параметры скачивания. Командную строку yt-dlp сервер собирает из них сам
+/// (ytdlp_options::build_ytdlp_args), произвольные аргументы от клиента не принимаются.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DownloadOptions {
+    /// Селектор формата, передаётся в yt-dlp как -f (например "bestaudio")
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Формат аудио, передаётся в yt-dlp как --audio-format; по умолчанию Config::audio_format
+    #[serde(default = "default_audio_format")]
+    pub audio_format: String,
+    /// Качество аудио для --audio-quality: от 0 (лучшее) до 10 или битрейт вида "192K"
+    #[serde(default)]
+    pub audio_quality: Option<String>,
+    /// Путь к итоговому файлу относительно download_path;
+    /// по умолчанию "Artist - Title/Artist - Title.<audio_format>"
+    #[serde(default)]
+    pub output: Option<String>,
+    /// --embed-thumbnail
+    #[serde(default)]
+    pub embed_thumbnail: bool,
+    /// --embed-metadata
+    #[serde(default = "default_embed_metadata")]
+    pub embed_metadata: bool,
+    /// Обработка громкости: "off", "replay_gain" или "normalize"; по умолчанию Config::loudness
+    #[serde(default = "default_loudness")]
+    pub loudness: LoudnessMode,
+    /// Целевая громкость для "normalize", LUFS; по умолчанию Config::loudness_target_lufs
+    #[serde(default = "default_target_lufs")]
+    pub target_lufs: f64,
+}
+
+/// Формат по умолчанию — Config::audio_format
+fn default_audio_format() -> String {
+    Config::get()
+        .map(|config| config.audio_format.clone())
+        .unwrap_or_else(|_| "mp3".to_string())
+}
+
+fn default_embed_metadata() -> bool {
+    true
+}
+
+fn default_loudness() -> LoudnessMode {
+    Config::get()
+        .map(|config| config.loudness)
+        .unwrap_or_default()
+}
+
+fn default_target_lufs() -> f64 {
+    Config::get()
+        .map(|config| config.loudness_target_lufs)
+        .unwrap_or(-14.0)
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        DownloadOptions {
+            format: None,
+            audio_format: default_audio_format(),
+            audio_quality: None,
+            output: None,
+            embed_thumbnail: false,
+            embed_metadata: default_embed_metadata(),
+            loudness: default_loudness(),
+            target_lufs: default_target_lufs(),
+        }
+    }
+}
+
+/// Ошибка валидации одного поля запроса.
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
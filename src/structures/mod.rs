@@ -1,3 +1,5 @@
 pub mod structs_git;
 
+pub mod download_request;
+pub mod track_metadata;
 pub mod vk_data;
@@ -0,0 +1,193 @@
+use crate::loudness::ReplayGain;
+use chrono::Datelike;
+use serde_json::{Map, Value};
+
+/// Метаданные трека из json-data (metadata запроса), которые записываются в теги файла.
+/// Ключи принимаются как в snake_case, так и в camelCase расширения; числа — числом или строкой.
+/// Поле неверного типа пропускается с предупреждением: теги без него лучше, чем отказ в загрузке.
+#[derive(Debug, Clone, Default)]
+pub struct TrackMetadata {
+    pub artist: String,
+    pub title: String,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track: Option<u32>,
+    pub track_total: Option<u32>,
+    pub disc: Option<u32>,
+    pub disc_total: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+    /// Длительность в секундах
+    pub duration: Option<u32>,
+    /// ID владельца аудиозаписи ВКонтакте (у сообществ отрицательный)
+    pub owner_id: Option<i64>,
+    /// ID аудиозаписи ВКонтакте
+    pub audio_id: Option<i64>,
+    /// ReplayGain трека — из этапа громкости, а не из json-data
+    pub replay_gain: Option<ReplayGain>,
+}
+
+/// Самый ранний год, который записывается в теги
+const MIN_YEAR: u32 = 1000;
+
+/// Чтение полей из json-data; поля неверного типа пропускаются.
+struct Fields<'a> {
+    map: &'a Map<String, Value>,
+}
+
+impl<'a> Fields<'a> {
+    /// Первый из ключей `keys`, который есть в json-data и не null.
+    fn get(&self, keys: &[&'static str]) -> Option<(&'static str, &'a Value)> {
+        keys.iter()
+            .find_map(|&key| self.map.get(key).filter(|v| !v.is_null()).map(|v| (key, v)))
+    }
+
+    /// Предупреждает, что поле `key` пропущено.
+    fn warn(&self, key: &str, message: &str) {
+        eprintln!("Ignoring metadata.{}: {}", key, message);
+    }
+
+    /// Строка без пробелов по краям; пустая строка — значения нет.
+    /// Объект VK API (например album: {"id": .., "title": ..}) даёт свой title.
+    fn text(&self, keys: &[&'static str]) -> Option<String> {
+        let (key, value) = self.get(keys)?;
+        let value = match value {
+            Value::Object(object) => object.get("title").unwrap_or(&Value::Null),
+            value => value,
+        };
+        match value {
+            Value::String(s) => Some(s.trim().to_string()).filter(|s| !s.is_empty()),
+            Value::Number(n) => Some(n.to_string()),
+            _ => {
+                self.warn(key, "must be a string");
+                None
+            }
+        }
+    }
+
+    /// Целое число: 3 или "3"; дробное (длительность 215.4) округляется.
+    fn integer<T: TryFrom<i64>>(&self, keys: &[&'static str]) -> Option<T> {
+        let (key, value) = self.get(keys)?;
+        let parsed = match value {
+            Value::Number(n) => n.as_i64().or_else(|| n.as_f64().map(|f| f.round() as i64)),
+            Value::String(s) if s.trim().is_empty() => return None,
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        };
+        match parsed.and_then(|n| T::try_from(n).ok()) {
+            Some(n) => Some(n),
+            None => {
+                self.warn(key, "must be an integer in range");
+                None
+            }
+        }
+    }
+
+    /// Номер с общим количеством: 3, "3" или "3/12".
+    fn position(&self, keys: &[&'static str]) -> (Option<u32>, Option<u32>) {
+        if let Some((key, Value::String(s))) = self.get(keys)
+            && let Some((number, total)) = s.split_once('/')
+        {
+            return match (number.trim().parse(), total.trim().parse()) {
+                (Ok(number), Ok(total)) => (Some(number), Some(total)),
+                _ => {
+                    self.warn(key, "must be a number or \"number/total\"");
+                    (None, None)
+                }
+            };
+        }
+        (self.integer(keys), None)
+    }
+
+    /// Год из четырёх цифр не позже следующего: 2019, "2019" или дата "2019-05-01".
+    fn year(&self, keys: &[&'static str]) -> Option<u32> {
+        let (key, value) = self.get(keys)?;
+        let year = match value {
+            Value::Number(n) => n.as_u64().and_then(|n| u32::try_from(n).ok()),
+            Value::String(s) => {
+                let s = s.trim();
+                let year = s.split_once('-').map_or(s, |(year, _)| year);
+                year.parse().ok().filter(|_| year.len() == 4)
+            }
+            _ => None,
+        };
+        let max_year = chrono::Utc::now().year() as u32 + 1;
+        let year = year.filter(|year| (MIN_YEAR..=max_year).contains(year));
+        if year.is_none() {
+            self.warn(key, "must be a four-digit year or a YYYY-MM-DD date");
+        }
+        year
+    }
+}
+
+impl TrackMetadata {
+    /// Разбирает json-data; `artist` и `title` запроса имеют приоритет над safeArtist/safeTitle.
+    /// `date` из VK API — Unix-время добавления записи, а не год выпуска, поэтому год берётся
+    /// только из `year`.
+    pub fn from_json(metadata: &Map<String, Value>, artist: &str, title: &str) -> TrackMetadata {
+        let fields = Fields { map: metadata };
+        let or_field = |value: &str, fields: &Fields, keys| {
+            Some(value.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .or_else(|| fields.text(keys))
+                .unwrap_or_default()
+        };
+        let artist = or_field(artist, &fields, &["artist", "safeArtist"]);
+        let title = or_field(title, &fields, &["title", "safeTitle"]);
+        let (track, track_total) = fields.position(&["track", "trackNumber"]);
+        let (disc, disc_total) = fields.position(&["disc", "discNumber"]);
+        let track_total = track_total.or_else(|| fields.integer(&["track_total", "trackTotal"]));
+        let disc_total = disc_total.or_else(|| fields.integer(&["disc_total", "discTotal"]));
+        TrackMetadata {
+            artist,
+            title,
+            album: fields.text(&["album"]),
+            album_artist: fields.text(&["album_artist", "albumArtist"]),
+            track,
+            track_total,
+            disc,
+            disc_total,
+            year: fields.year(&["year"]),
+            genre: fields.text(&["genre"]),
+            duration: fields.integer(&["duration"]),
+            owner_id: fields.integer(&["owner_id", "ownerId"]),
+            audio_id: fields.integer(&["audio_id", "audioId"]),
+            replay_gain: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(metadata: Value) -> TrackMetadata {
+        TrackMetadata::from_json(metadata.as_object().unwrap(), "Artist", "Title")
+    }
+
+    #[test]
+    fn invalid_fields_are_dropped() {
+        let metadata = parse(json!({
+            "duration": "3:45",
+            "track": "2/12",
+            "album": ["not", "a", "string"],
+            "genre": "Rock",
+        }));
+        assert_eq!(metadata.duration, None);
+        assert_eq!(metadata.album, None);
+        assert_eq!((metadata.track, metadata.track_total), (Some(2), Some(12)));
+        assert_eq!(metadata.genre.as_deref(), Some("Rock"));
+    }
+
+    #[test]
+    fn year_is_a_plausible_four_digit_year() {
+        assert_eq!(parse(json!({ "year": 2019 })).year, Some(2019));
+        assert_eq!(parse(json!({ "year": "2019-05-01" })).year, Some(2019));
+        assert_eq!(parse(json!({ "year": 20190 })).year, None);
+        assert_eq!(parse(json!({ "year": "19" })).year, None);
+        assert_eq!(parse(json!({ "year": 9999 })).year, None);
+        // date в VK API — Unix-время, а не год
+        assert_eq!(parse(json!({ "date": 1556668800 })).year, None);
+    }
+}
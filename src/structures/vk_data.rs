@@ -2,18 +2,18 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
 pub struct Data {
+    #[serde(default, rename = "safeArtist")]
+    pub safe_artist: String,
+    #[serde(default, rename = "safeTitle")]
+    pub safe_title: String,
     #[serde(default)]
-    pub safeArtist: String,
-    #[serde(default)]
-    pub safeTitle: String,
-    #[serde(default)]
-    pub image: String,
-    #[serde(default)]
-    pub index: String,
+    pub index: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct Demo {
-    pub safeArtistTitle: String,
-    pub Uri: String,
+    #[serde(rename = "safeArtistTitle")]
+    pub safe_artist_title: String,
+    #[serde(rename = "Uri")]
+    pub uri: String,
 }
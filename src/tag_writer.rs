@@ -0,0 +1,343 @@
+use crate::loudness::ReplayGain;
+use crate::structures::track_metadata::TrackMetadata;
+use anyhow::Context;
+use lofty::TextEncoding;
+use lofty::config::{ParseOptions, WriteOptions};
+use lofty::file::AudioFile;
+use lofty::flac::FlacFile;
+use lofty::id3::v2::{Frame, FrameId, Id3v2Tag, TextInformationFrame};
+use lofty::mp4::{Atom, AtomData, AtomIdent, Ilst, Mp4File};
+use lofty::mpeg::MpegFile;
+use lofty::ogg::{OggPictureStorage, OpusFile, VorbisComments, VorbisFile};
+use lofty::picture::{Picture, PictureType};
+use lofty::tag::{Accessor, TagExt};
+use std::borrow::Cow;
+use std::fs::File;
+use std::path::Path;
+
+/// Имена полей с идентификаторами ВКонтакте (TXXX в ID3v2, поля Vorbis, freeform-атомы MP4)
+const VK_OWNER_ID: &str = "VK_OWNER_ID";
+const VK_AUDIO_ID: &str = "VK_AUDIO_ID";
+
+/// Поля ReplayGain (TXXX в ID3v2, поля Vorbis; в MP4 — freeform-атомы в нижнем регистре)
+const REPLAYGAIN_TRACK_GAIN: &str = "REPLAYGAIN_TRACK_GAIN";
+const REPLAYGAIN_TRACK_PEAK: &str = "REPLAYGAIN_TRACK_PEAK";
+
+/// Opus вместо ReplayGain использует R128_TRACK_GAIN: усиление до -23 LUFS в формате Q7.8
+/// (RFC 7845), т.е. на 5 дБ меньше, чем до опорных -18 LUFS ReplayGain
+const R128_TRACK_GAIN: &str = "R128_TRACK_GAIN";
+const R128_REFERENCE_OFFSET_DB: f64 = -5.0;
+
+/// Пространство имён freeform-атомов MP4 ("----:com.apple.iTunes:<имя>")
+const MP4_FREEFORM_MEAN: &str = "com.apple.iTunes";
+
+/// Формат файла с точки зрения тегов: у каждого свой тег и способ хранения обложки.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TagFormat {
+    /// ID3v2, обложка — кадр APIC
+    Mp3,
+    /// Vorbis comments, обложка — METADATA_BLOCK_PICTURE
+    Opus,
+    Vorbis,
+    /// Атомы ilst, обложка — атом covr
+    M4a,
+    /// Vorbis comments, обложка — блок PICTURE
+    Flac,
+}
+
+impl TagFormat {
+    /// Формат по расширению файла; None — теги для него не пишутся (wav и т.п.).
+    pub fn from_path(path: &Path) -> Option<TagFormat> {
+        let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "mp3" => Some(TagFormat::Mp3),
+            "opus" => Some(TagFormat::Opus),
+            "ogg" => Some(TagFormat::Vorbis),
+            "m4a" | "mp4" => Some(TagFormat::M4a),
+            "flac" => Some(TagFormat::Flac),
+            _ => None,
+        }
+    }
+}
+
+/// Общие для всех форматов поля: название, исполнитель, альбом, номера трека/диска, год, жанр.
+/// Поля, которых нет в метаданных, не трогаются — остаются значения, записанные yt-dlp
+/// (--embed-metadata).
+fn apply_common(tag: &mut impl Accessor, metadata: &TrackMetadata) {
+    tag.set_title(metadata.title.clone());
+    tag.set_artist(metadata.artist.clone());
+    if let Some(album) = &metadata.album {
+        tag.set_album(album.clone());
+    }
+    if let Some(track) = metadata.track {
+        tag.set_track(track);
+    }
+    if let Some(total) = metadata.track_total {
+        tag.set_track_total(total);
+    }
+    if let Some(disc) = metadata.disc {
+        tag.set_disk(disc);
+    }
+    if let Some(total) = metadata.disc_total {
+        tag.set_disk_total(total);
+    }
+    if let Some(year) = metadata.year {
+        tag.set_year(year);
+    }
+    if let Some(genre) = &metadata.genre {
+        tag.set_genre(genre.clone());
+    }
+}
+
+/// Значения ReplayGain в принятом виде: "-3.21 dB" и "0.987654".
+fn replay_gain_text(replay_gain: &ReplayGain) -> (String, String) {
+    (
+        format!("{:.2} dB", replay_gain.track_gain_db),
+        format!("{:.6}", replay_gain.track_peak),
+    )
+}
+
+/// Обложка как "Cover (front)".
+fn front_cover(mut cover: &[u8]) -> anyhow::Result<Picture> {
+    let mut picture = Picture::from_reader(&mut cover).context("unsupported cover image")?;
+    picture.set_pic_type(PictureType::CoverFront);
+    Ok(picture)
+}
+
+/// Текстовый кадр ID3v2 `id` (TPE2, TLEN, ...) в UTF-16: UTF-8 в ID3v2.3 не поддерживается.
+fn text_frame(id: &'static str, value: String) -> Frame<'static> {
+    Frame::Text(TextInformationFrame::new(
+        FrameId::Valid(Cow::Borrowed(id)),
+        TextEncoding::UTF16,
+        value,
+    ))
+}
+
+/// ID3v2.3 (его читают и старые плееры, и Windows): общие поля, TPE2, TLEN, TXXX с ID ВКонтакте и ReplayGain, обложка APIC.
+/// Прочие кадры, которые оставил yt-dlp, сохраняются; аудиокадры не перекодируются и не копируются.
+fn write_mp3_tags(
+    path: &Path,
+    metadata: &TrackMetadata,
+    cover: Option<&[u8]>,
+) -> anyhow::Result<()> {
+    let mpeg = MpegFile::read_from(&mut File::open(path)?, ParseOptions::new())?;
+    let mut tag = mpeg.id3v2().cloned().unwrap_or_else(Id3v2Tag::new);
+    apply_common(&mut tag, metadata);
+    if let Some(album_artist) = &metadata.album_artist {
+        tag.insert(text_frame("TPE2", album_artist.clone()));
+    }
+    if let Some(duration) = metadata.duration {
+        // TLEN — в миллисекундах
+        tag.insert(text_frame("TLEN", (u64::from(duration) * 1000).to_string()));
+    }
+    if let Some(owner_id) = metadata.owner_id {
+        tag.insert_user_text(VK_OWNER_ID.to_string(), owner_id.to_string());
+    }
+    if let Some(audio_id) = metadata.audio_id {
+        tag.insert_user_text(VK_AUDIO_ID.to_string(), audio_id.to_string());
+    }
+    if let Some(replay_gain) = &metadata.replay_gain {
+        let (gain, peak) = replay_gain_text(replay_gain);
+        tag.insert_user_text(REPLAYGAIN_TRACK_GAIN.to_string(), gain);
+        tag.insert_user_text(REPLAYGAIN_TRACK_PEAK.to_string(), peak);
+    }
+    if let Some(cover) = cover {
+        tag.remove_picture_type(PictureType::CoverFront);
+        tag.insert_picture(front_cover(cover)?);
+    }
+    tag.save_to_path(path, WriteOptions::default().use_id3v23(true))?;
+    Ok(())
+}
+
+/// Vorbis comments: общие поля, ALBUMARTIST, поля с ID ВКонтакте и ReplayGain
+/// (для Opus — R128_TRACK_GAIN).
+fn apply_vorbis_comments(tag: &mut VorbisComments, format: TagFormat, metadata: &TrackMetadata) {
+    apply_common(tag, metadata);
+    if let Some(album_artist) = &metadata.album_artist {
+        tag.insert("ALBUMARTIST".to_string(), album_artist.clone());
+    }
+    if let Some(owner_id) = metadata.owner_id {
+        tag.insert(VK_OWNER_ID.to_string(), owner_id.to_string());
+    }
+    if let Some(audio_id) = metadata.audio_id {
+        tag.insert(VK_AUDIO_ID.to_string(), audio_id.to_string());
+    }
+    match &metadata.replay_gain {
+        Some(replay_gain) if format == TagFormat::Opus => {
+            let q78 = ((replay_gain.track_gain_db + R128_REFERENCE_OFFSET_DB) * 256.0)
+                .round()
+                .clamp(i16::MIN.into(), i16::MAX.into());
+            tag.insert(R128_TRACK_GAIN.to_string(), (q78 as i16).to_string());
+        }
+        Some(replay_gain) => {
+            let (gain, peak) = replay_gain_text(replay_gain);
+            tag.insert(REPLAYGAIN_TRACK_GAIN.to_string(), gain);
+            tag.insert(REPLAYGAIN_TRACK_PEAK.to_string(), peak);
+        }
+        None => {}
+    }
+}
+
+/// Ogg (Opus/Vorbis): Vorbis comments, обложка — поле METADATA_BLOCK_PICTURE.
+/// Страницы Ogg с аудио переписываются без перекодирования.
+fn write_ogg_tags(
+    path: &Path,
+    format: TagFormat,
+    metadata: &TrackMetadata,
+    cover: Option<&[u8]>,
+) -> anyhow::Result<()> {
+    let mut file = File::open(path)?;
+    let mut tag = match format {
+        TagFormat::Opus => OpusFile::read_from(&mut file, ParseOptions::new())?
+            .vorbis_comments()
+            .clone(),
+        _ => VorbisFile::read_from(&mut file, ParseOptions::new())?
+            .vorbis_comments()
+            .clone(),
+    };
+    drop(file);
+    apply_vorbis_comments(&mut tag, format, metadata);
+    if let Some(cover) = cover {
+        tag.remove_picture_type(PictureType::CoverFront);
+        tag.insert_picture(front_cover(cover)?, None)?;
+    }
+    tag.save_to_path(path, WriteOptions::default())?;
+    Ok(())
+}
+
+/// FLAC: Vorbis comments, обложка — отдельный блок PICTURE (а не поле в комментариях).
+fn write_flac_tags(
+    path: &Path,
+    metadata: &TrackMetadata,
+    cover: Option<&[u8]>,
+) -> anyhow::Result<()> {
+    let mut flac = FlacFile::read_from(&mut File::open(path)?, ParseOptions::new())?;
+    let mut tag = flac.remove_vorbis_comments().unwrap_or_default();
+    apply_vorbis_comments(&mut tag, TagFormat::Flac, metadata);
+    if let Some(cover) = cover {
+        tag.remove_picture_type(PictureType::CoverFront);
+        flac.remove_picture_type(PictureType::CoverFront);
+        flac.insert_picture(front_cover(cover)?, None)?;
+    }
+    flac.set_vorbis_comments(tag);
+    flac.save_to_path(path, WriteOptions::default())?;
+    Ok(())
+}
+
+/// Строковый атом MP4.
+fn text_atom(ident: AtomIdent<'static>, value: String) -> Atom<'static> {
+    Atom::new(ident, AtomData::UTF8(value))
+}
+
+/// Freeform-атом "----:com.apple.iTunes:<name>".
+fn freeform(name: &str) -> AtomIdent<'static> {
+    AtomIdent::Freeform {
+        mean: Cow::Borrowed(MP4_FREEFORM_MEAN),
+        name: Cow::Owned(name.to_string()),
+    }
+}
+
+/// M4A: атомы ilst (aART, freeform-атомы с ID ВКонтакте и ReplayGain), обложка — атом covr.
+fn write_m4a_tags(
+    path: &Path,
+    metadata: &TrackMetadata,
+    cover: Option<&[u8]>,
+) -> anyhow::Result<()> {
+    let mp4 = Mp4File::read_from(&mut File::open(path)?, ParseOptions::new())?;
+    let mut tag = mp4.ilst().cloned().unwrap_or_else(Ilst::new);
+    apply_common(&mut tag, metadata);
+    if let Some(album_artist) = &metadata.album_artist {
+        tag.replace_atom(text_atom(AtomIdent::Fourcc(*b"aART"), album_artist.clone()));
+    }
+    if let Some(owner_id) = metadata.owner_id {
+        tag.replace_atom(text_atom(freeform(VK_OWNER_ID), owner_id.to_string()));
+    }
+    if let Some(audio_id) = metadata.audio_id {
+        tag.replace_atom(text_atom(freeform(VK_AUDIO_ID), audio_id.to_string()));
+    }
+    if let Some(replay_gain) = &metadata.replay_gain {
+        let (gain, peak) = replay_gain_text(replay_gain);
+        let gain_name = REPLAYGAIN_TRACK_GAIN.to_ascii_lowercase();
+        let peak_name = REPLAYGAIN_TRACK_PEAK.to_ascii_lowercase();
+        tag.replace_atom(text_atom(freeform(&gain_name), gain));
+        tag.replace_atom(text_atom(freeform(&peak_name), peak));
+    }
+    if let Some(cover) = cover {
+        // covr не хранит тип картинки — заменяем все
+        tag.remove_pictures();
+        tag.insert_picture(front_cover(cover)?);
+    }
+    tag.save_to_path(path, WriteOptions::default())?;
+    Ok(())
+}
+
+/// Записывает метаданные трека и обложку (JPEG/PNG) в файл `path` тегами его формата.
+pub fn write_tags(
+    path: &Path,
+    metadata: &TrackMetadata,
+    cover: Option<&[u8]>,
+) -> anyhow::Result<()> {
+    let format = TagFormat::from_path(path)
+        .with_context(|| format!("tagging {} is not supported", path.display()))?;
+    match format {
+        TagFormat::Mp3 => write_mp3_tags(path, metadata, cover),
+        TagFormat::Opus | TagFormat::Vorbis => write_ogg_tags(path, format, metadata, cover),
+        TagFormat::M4a => write_m4a_tags(path, metadata, cover),
+        TagFormat::Flac => write_flac_tags(path, metadata, cover),
+    }
+    .with_context(|| format!("Failed to tag {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lofty::id3::v2::Id3v2Version;
+
+    /// MP3 без тегов: десять пустых кадров MPEG-1 Layer III, 128 кбит/с, 44.1 кГц.
+    fn silent_mp3(path: &Path) {
+        let mut frame = vec![0u8; 417];
+        frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+        std::fs::write(path, frame.repeat(10)).unwrap();
+    }
+
+    #[test]
+    fn mp3_tags_are_id3v23_and_keep_cyrillic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("track.mp3");
+        silent_mp3(&path);
+        // как после yt-dlp: ID3v2.4 с кадрами в UTF-8
+        let mut existing = Id3v2Tag::new();
+        existing.set_album("Группа крови".to_string());
+        existing
+            .save_to_path(&path, WriteOptions::default())
+            .unwrap();
+        let metadata = TrackMetadata {
+            artist: "Кино".to_string(),
+            title: "Группа крови".to_string(),
+            album_artist: Some("Виктор Цой".to_string()),
+            duration: Some(285),
+            owner_id: Some(-42),
+            ..Default::default()
+        };
+        write_tags(&path, &metadata, None).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        assert_eq!(&bytes[..5], b"ID3\x03\x00");
+        let mpeg =
+            MpegFile::read_from(&mut File::open(&path).unwrap(), ParseOptions::new()).unwrap();
+        let tag = mpeg.id3v2().unwrap();
+        assert_eq!(tag.original_version(), Id3v2Version::V3);
+        assert_eq!(tag.artist().as_deref(), Some("Кино"));
+        assert_eq!(tag.title().as_deref(), Some("Группа крови"));
+        assert_eq!(tag.album().as_deref(), Some("Группа крови"));
+        assert_eq!(
+            tag.get_text(&FrameId::Valid(Cow::Borrowed("TPE2"))),
+            Some("Виктор Цой")
+        );
+        assert_eq!(
+            tag.get_text(&FrameId::Valid(Cow::Borrowed("TLEN"))),
+            Some("285000")
+        );
+        assert_eq!(tag.get_user_text(VK_OWNER_ID), Some("-42"));
+    }
+}
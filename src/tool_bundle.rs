@@ -0,0 +1,311 @@
+use crate::archive_extractor::{ArchiveKind, ExtractOptions, extract_archive};
+use crate::checksum::sha256_file;
+use crate::tool_installer::{AssetSource, ReleaseCandidate, install_candidate_async};
+use crate::tool_locator::{BIN_DIR, resolve};
+use crate::tool_manifest::{MANIFEST_FILE, ToolManifest, ToolRecord};
+use crate::tool_spec::{
+    REQUIRED_TOOLS, ToolSpec, find_spec, is_plain_file_name, sanitize_file_name,
+};
+use anyhow::{Context, bail};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+// Офлайн-набор инструментов — каталог (или архив с ним) с tools.json в формате ToolManifest
+// и файлами, указанными в поле asset каждой записи. export_bundle создаёт его из BIN_DIR:
+// каждый инструмент упаковывается в "<имя>-<тег>.tar.gz" с файлами без каталогов;
+// sha256 записи остаётся суммой исходного asset'а релиза, сумма архива — в bundle_sha256.
+
+/// Каталоги BIN_DIR для распаковки набора-архива и для сборки экспорта
+const BUNDLE_DIR: &str = ".bundle";
+const EXPORT_DIR: &str = ".export";
+
+/// Инструмент из набора, прошедший проверку.
+struct BundleEntry {
+    spec: ToolSpec,
+    candidate: ReleaseCandidate,
+    file: PathBuf,
+}
+
+/// Каталог с tools.json: сам `dir` или его единственный подкаталог (архив с корневой папкой).
+fn find_bundle_root(dir: &Path) -> anyhow::Result<PathBuf> {
+    if dir.join(MANIFEST_FILE).is_file() {
+        return Ok(dir.to_path_buf());
+    }
+    let subdirs: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    match subdirs.as_slice() {
+        [only] if only.join(MANIFEST_FILE).is_file() => Ok(only.clone()),
+        _ => bail!("{} not found in bundle {}", MANIFEST_FILE, dir.display()),
+    }
+}
+
+/// Открывает набор: каталог используется как есть, архив распаковывается в BIN_DIR/.bundle
+/// с обычными ограничениями распаковки. Возвращает каталог с tools.json.
+async fn open_bundle_async(bundle: &Path) -> anyhow::Result<PathBuf> {
+    if bundle.is_dir() {
+        return find_bundle_root(bundle);
+    }
+    let name = bundle.file_name().unwrap_or_default().to_string_lossy();
+    let kind = ArchiveKind::from_name(&name)
+        .with_context(|| format!("{} is not a directory or a supported archive", name))?;
+    let dest = Path::new(BIN_DIR).join(BUNDLE_DIR);
+    let archive = bundle.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let _ = fs::remove_dir_all(&dest);
+        fs::create_dir_all(&dest)?;
+        extract_archive(kind, &archive, &dest, &ExtractOptions::default())
+            .context("Failed to extract tool bundle")?;
+        find_bundle_root(&dest)
+    })
+    .await?
+}
+
+/// SHA-256, которому должен соответствовать файл `record` из набора, если он где-то закреплён:
+/// ToolSpec::sha256 или запись того же тега и asset'а в локальном tools.json (`installed`).
+/// Такой файл — исходный asset релиза, а не перепакованный export_bundle.
+fn pinned_sha256(spec: &ToolSpec, record: &ToolRecord, installed: &ToolManifest) -> Option<String> {
+    spec.sha256.clone().or_else(|| {
+        installed
+            .tools
+            .get(&spec.name)
+            .into_iter()
+            .chain(installed.previous.get(&spec.name))
+            .find(|r| r.tag == record.tag && r.asset == record.asset && !r.sha256.is_empty())
+            .map(|r| r.sha256.clone())
+    })
+}
+
+/// SHA-256 в hex.
+fn is_sha256(value: &str) -> bool {
+    value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Проверяет набор целиком, прежде чем что-либо устанавливать: каждый инструмент описан
+/// (встроенный или из extra_tools), файл на месте, есть SHA-256 и целевой исполняемый файл,
+/// а обязательные инструменты есть в наборе или уже установлены.
+/// Файл с ToolRecord::bundle_sha256 — перепаковка export_bundle: проверяется по этой сумме,
+/// а в tools.json записывается sha256 исходного asset'а.
+/// Если SHA-256 закреплён (pinned_sha256), файл — исходный asset релиза и проверяется по нему,
+/// а не по tools.json набора.
+fn validate_bundle(
+    root: &Path,
+    manifest: &ToolManifest,
+    installed: &ToolManifest,
+) -> anyhow::Result<Vec<BundleEntry>> {
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+    for (name, record) in &manifest.tools {
+        let Some(spec) = find_spec(name) else {
+            errors.push(format!("unknown tool {}: describe it in extra_tools", name));
+            continue;
+        };
+        let file = root.join(&record.asset);
+        let repack = record.bundle_sha256.as_deref();
+        let file_sha256 = repack.unwrap_or(&record.sha256);
+        let pinned = match repack {
+            // у перепаковки проверяется только ToolSpec::sha256: с ним набор отклоняется ниже
+            Some(_) => spec.sha256.clone(),
+            None => pinned_sha256(&spec, record, installed),
+        };
+        if !is_plain_file_name(&record.asset) {
+            errors.push(format!("{}: invalid asset name {:?}", name, record.asset));
+        } else if !file.is_file() {
+            errors.push(format!("{}: {} is missing", name, record.asset));
+        } else if !is_sha256(file_sha256) || !is_sha256(&record.sha256) {
+            errors.push(format!("{}: no valid SHA-256 for {}", name, record.asset));
+        } else if repack.is_some() && pinned.is_some() {
+            errors.push(format!(
+                "{}: its tool spec pins the SHA-256 of the release asset; \
+                 bundle the original asset instead of {}",
+                name, record.asset
+            ));
+        } else if let Some(pinned) = pinned
+            .as_deref()
+            .filter(|pinned| !pinned.eq_ignore_ascii_case(&record.sha256))
+        {
+            errors.push(format!(
+                "{}: bundle lists SHA-256 {} for {}, but {} is pinned",
+                name, record.sha256, record.asset, pinned
+            ));
+        } else if pinned.is_none() && !record.files.contains(&spec.target_name()) {
+            errors.push(format!(
+                "{}: bundle does not contain {}",
+                name,
+                spec.target_name()
+            ));
+        } else {
+            let spec = match &pinned {
+                // закреплённая сумма — значит, в наборе исходный asset релиза:
+                // он распаковывается по правилам из описания
+                Some(pinned) => ToolSpec {
+                    sha256: Some(pinned.clone()),
+                    ..spec
+                },
+                // файлы набора лежат без каталогов — правила распаковки релиза к ним не относятся
+                None => ToolSpec {
+                    archive: None,
+                    extract: Vec::new(),
+                    sha256: Some(file_sha256.to_string()),
+                    ..spec
+                },
+            };
+            let sha256 = pinned.unwrap_or_else(|| file_sha256.to_string());
+            let candidate = ReleaseCandidate {
+                tag: record.tag.clone(),
+                asset_name: record.asset.clone(),
+                url: file.to_string_lossy().into_owned(),
+                size: Some(record.size),
+                sha256: Some(sha256.to_ascii_lowercase()),
+                upstream_sha256: repack.map(|_| record.sha256.to_ascii_lowercase()),
+            };
+            entries.push(BundleEntry {
+                spec,
+                candidate,
+                file,
+            });
+        }
+    }
+    for name in REQUIRED_TOOLS {
+        if !manifest.tools.contains_key(name) && resolve(name).is_none() {
+            errors.push(format!("{} is neither in the bundle nor installed", name));
+        }
+    }
+    if entries.is_empty() && errors.is_empty() {
+        errors.push("bundle contains no tools".to_string());
+    }
+    if !errors.is_empty() {
+        bail!("invalid tool bundle: {}", errors.join("; "));
+    }
+    Ok(entries)
+}
+
+/// Устанавливает инструменты из офлайн-набора `bundle` (каталог или архив) с теми же проверками,
+/// что и при загрузке: размер, SHA-256, распаковка, подмена файлов с сохранением прежней версии.
+/// `missing_only` — ставить только то, чего нет ни в BIN_DIR, ни в PATH.
+/// Возвращает имена установленных инструментов.
+pub async fn install_bundle_async(
+    bundle: &Path,
+    missing_only: bool,
+) -> anyhow::Result<Vec<String>> {
+    let root = open_bundle_async(bundle).await?;
+    let manifest = ToolManifest::load_from(&root.join(MANIFEST_FILE))?;
+    let result = async {
+        let mut installed = Vec::new();
+        let installed_manifest = ToolManifest::load().unwrap_or_default();
+        for entry in validate_bundle(&root, &manifest, &installed_manifest)? {
+            if missing_only && resolve(&entry.spec.name).is_some() {
+                continue;
+            }
+            install_candidate_async(
+                AssetSource::Local(&entry.file),
+                &entry.spec,
+                entry.candidate,
+            )
+            .await?;
+            installed.push(entry.spec.name);
+        }
+        Ok(installed)
+    }
+    .await;
+    let _ = tokio::fs::remove_dir_all(Path::new(BIN_DIR).join(BUNDLE_DIR)).await;
+    result
+}
+
+/// Имя файла инструмента в наборе: "<имя>-<тег>.tar.gz" (тег "local", если его нет).
+fn bundle_asset_name(name: &str, tag: &str) -> String {
+    match tag {
+        "" => format!("{}-local.tar.gz", name),
+        tag => format!("{}-{}.tar.gz", name, sanitize_file_name(tag)),
+    }
+}
+
+/// Упаковывает файлы `files` из `base` в tar.gz без каталогов.
+fn pack_tar_gz(archive: &Path, base: &Path, files: &[String]) -> anyhow::Result<()> {
+    let mut builder = tar::Builder::new(GzEncoder::new(
+        File::create(archive).with_context(|| format!("Failed to create {}", archive.display()))?,
+        Compression::default(),
+    ));
+    for file in files {
+        builder
+            .append_path_with_name(base.join(file), file)
+            .with_context(|| format!("Failed to pack {}", file))?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Создаёт офлайн-набор из инструментов, записанных в tools.json:
+/// `dest` — каталог или файл ".tar.gz". Инструменты, файлы которых пропали из BIN_DIR,
+/// и инструменты с закреплённым ToolSpec::sha256 пропускаются с предупреждением.
+/// Возвращает имена упакованных инструментов.
+pub fn export_bundle(dest: &Path) -> anyhow::Result<Vec<String>> {
+    let manifest = ToolManifest::load()?;
+    if manifest.tools.is_empty() {
+        bail!("no tools recorded in {}", ToolManifest::path().display());
+    }
+    let name = dest.file_name().unwrap_or_default().to_string_lossy();
+    let archive = match ArchiveKind::from_name(&name) {
+        Some(ArchiveKind::TarGz) => true,
+        Some(_) => bail!("bundles can only be written as a directory or a .tar.gz file"),
+        None => false,
+    };
+    let bin = Path::new(BIN_DIR);
+    let dir = if archive {
+        bin.join(EXPORT_DIR)
+    } else {
+        dest.to_path_buf()
+    };
+    let _ = fs::remove_dir_all(bin.join(EXPORT_DIR));
+    fs::create_dir_all(&dir)?;
+
+    let mut tools = BTreeMap::new();
+    for (name, record) in &manifest.tools {
+        if let Some(missing) = record.files.iter().find(|f| !bin.join(f).is_file()) {
+            eprintln!("Skipping {}: {} is missing from {}", name, missing, BIN_DIR);
+            continue;
+        }
+        if find_spec(name).is_some_and(|spec| spec.sha256.is_some()) {
+            eprintln!(
+                "Skipping {}: its tool spec pins the SHA-256 of the release asset, \
+                 which a repacked bundle cannot match; put the original asset into the bundle",
+                name
+            );
+            continue;
+        }
+        let asset = bundle_asset_name(name, &record.tag);
+        let path = dir.join(&asset);
+        pack_tar_gz(&path, bin, &record.files)?;
+        let exported = ToolRecord {
+            tag: record.tag.clone(),
+            asset,
+            size: fs::metadata(&path)?.len(),
+            sha256: record.sha256.clone(),
+            bundle_sha256: Some(sha256_file(&path)?),
+            installed_at: record.installed_at,
+            files: record.files.clone(),
+        };
+        tools.insert(name.clone(), exported);
+    }
+    if tools.is_empty() {
+        bail!("none of the recorded tools could be exported");
+    }
+    let exported: Vec<String> = tools.keys().cloned().collect();
+    let mut files: Vec<String> = tools.values().map(|r| r.asset.clone()).collect();
+    files.push(MANIFEST_FILE.to_string());
+    ToolManifest {
+        tools,
+        previous: BTreeMap::new(),
+    }
+    .save_to(&dir.join(MANIFEST_FILE))?;
+
+    if archive {
+        pack_tar_gz(dest, &dir, &files)?;
+        let _ = fs::remove_dir_all(&dir);
+    }
+    Ok(exported)
+}
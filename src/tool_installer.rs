@@ -0,0 +1,749 @@
+use crate::archive_extractor::{ArchiveKind, ExtractOptions, extract_archive};
+use crate::checksum::{expected_digest, find_checksum_asset, sha256_file};
+use crate::config_manager::Config;
+use crate::download_manager::download_file_async;
+use crate::github_releases::{GithubApi, ReleasePolicy, find_release_asset_async};
+use crate::http_client::http_client;
+use crate::structures::structs_git::Asset;
+use crate::tool_bundle::install_bundle_async;
+use crate::tool_locator::{BIN_DIR, bundled_path, missing_tools, resolve};
+use crate::tool_manifest::{ToolManifest, ToolRecord};
+use crate::tool_spec::{ToolSource, ToolSpec, find_spec, sanitize_file_name, tool_specs};
+use anyhow::{Context, anyhow, bail};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+use tokio::fs;
+use tokio::sync::Mutex;
+
+/// Подкаталоги BIN_DIR: сборка новой версии перед подменой и предыдущая версия для отката
+const STAGING_DIR: &str = ".staging";
+const PREVIOUS_DIR: &str = ".previous";
+
+/// Установка, обновление и откат меняют файлы в BIN_DIR — выполняем их по одной.
+static INSTALL_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Файл, выбранный для установки.
+pub(crate) struct ReleaseCandidate {
+    /// Тег релиза GitHub; для прямых ссылок пустой
+    pub tag: String,
+    pub asset_name: String,
+    pub url: String,
+    pub size: Option<u64>,
+    /// Ожидаемый SHA-256: из ToolSpec::sha256, из файла сумм релиза или из манифеста офлайн-набора.
+    /// None только при allow_unverified
+    pub sha256: Option<String>,
+    /// SHA-256 исходного asset'а релиза, если файл — его перепаковка из офлайн-набора;
+    /// записывается в tools.json вместо хэша самого файла
+    pub upstream_sha256: Option<String>,
+}
+
+/// Откуда взять файл кандидата.
+pub(crate) enum AssetSource<'a> {
+    /// Скачать по ReleaseCandidate::url
+    Download(&'a reqwest::Client),
+    /// Скопировать готовый файл из офлайн-набора (см. tool_bundle)
+    Local(&'a Path),
+}
+
+/// Результат проверки/обновления одного инструмента.
+#[derive(Debug, Serialize)]
+pub struct UpdateOutcome {
+    pub tool: String,
+    pub status: UpdateStatus,
+    /// Тег, установленный до обновления
+    pub installed: Option<String>,
+    /// Тег, найденный в релизах (последний или закреплённый в pinned_tools)
+    pub available: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateStatus {
+    UpToDate,
+    Updated,
+    Failed,
+}
+
+/// Тег, закреплённый для инструмента в Config::pinned_tools.
+fn pinned_tag(name: &str) -> Option<String> {
+    Config::get_unwrap().pinned_tools.get(name).cloned()
+}
+
+/// Находит файл для установки по описанию инструмента:
+/// - Github: релиз с закреплённым тегом `pin` или самый новый релиз (с учётом политики prerelease),
+///   где есть asset, подходящий под ToolSpec::asset_pattern (см. find_release_asset_async);
+///   SHA-256 — из ToolSpec::sha256 или файла сумм релиза;
+/// - Url: прямая ссылка, SHA-256 только из ToolSpec::sha256.
+///
+/// Без контрольной суммы возвращает ошибку, если в описании не разрешён allow_unverified.
+async fn find_release_async(
+    client: &reqwest::Client,
+    spec: &ToolSpec,
+    pin: Option<&str>,
+) -> anyhow::Result<ReleaseCandidate> {
+    spec.validate().map_err(|e| anyhow!(e))?;
+    let mut candidate = match &spec.source {
+        ToolSource::Github { repo } => find_github_asset_async(client, spec, repo, pin).await?,
+        ToolSource::Url { url } => ReleaseCandidate {
+            tag: String::new(),
+            asset_name: url
+                .split(['?', '#'])
+                .next()
+                .and_then(|path| path.rsplit('/').next())
+                .filter(|name| !name.is_empty())
+                .unwrap_or(&spec.name)
+                .to_string(),
+            url: url.clone(),
+            size: None,
+            sha256: None,
+            upstream_sha256: None,
+        },
+    };
+    if let Some(sha256) = &spec.sha256 {
+        candidate.sha256 = Some(sha256.to_ascii_lowercase());
+    }
+    if candidate.sha256.is_none() && !spec.allow_unverified {
+        bail!(
+            "no checksum for {}: set sha256 or allow_unverified in its tool spec",
+            candidate.asset_name
+        );
+    }
+    Ok(candidate)
+}
+
+async fn find_github_asset_async(
+    client: &reqwest::Client,
+    spec: &ToolSpec,
+    repo: &str,
+    pin: Option<&str>,
+) -> anyhow::Result<ReleaseCandidate> {
+    let pattern = spec.asset_pattern().with_context(|| {
+        format!(
+            "tool {} has no asset pattern for {}/{}",
+            spec.name,
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        )
+    })?;
+    let matcher =
+        Regex::new(pattern).with_context(|| format!("invalid asset pattern {}", pattern))?;
+    let policy = ReleasePolicy {
+        include_prereleases: spec.include_prereleases,
+    };
+    let api = GithubApi::from_config(Config::get()?);
+    let (release, asset) =
+        find_release_asset_async(client, &api, repo, &matcher, policy, pin).await?;
+    let tag = release.tag_name.clone().unwrap_or_default();
+
+    let url = asset
+        .browser_download_url
+        .clone()
+        .context("asset has no browser_download_url")?;
+    let sha256 = if spec.sha256.is_some() {
+        None
+    } else {
+        match fetch_expected_sha256(client, &release.assets, &asset.name).await {
+            Ok(sha256) => Some(sha256),
+            Err(_) if spec.allow_unverified => None,
+            Err(e) => return Err(e),
+        }
+    };
+
+    Ok(ReleaseCandidate {
+        tag,
+        asset_name: asset.name.clone(),
+        url,
+        size: asset.size,
+        sha256,
+        upstream_sha256: None,
+    })
+}
+
+/// Скачивает файл контрольных сумм релиза (SHA2-256SUMS, checksums.sha256 и т.п.)
+/// и возвращает ожидаемый SHA-256 для `asset_name`.
+/// Ошибка, если релиз не публикует суммы или в них нет записи для asset'а.
+async fn fetch_expected_sha256(
+    client: &reqwest::Client,
+    assets: &[Asset],
+    asset_name: &str,
+) -> anyhow::Result<String> {
+    let sums = find_checksum_asset(assets, asset_name)
+        .with_context(|| format!("release has no checksum file for {}", asset_name))?;
+    let url = sums
+        .browser_download_url
+        .as_deref()
+        .context("checksum asset has no browser_download_url")?;
+    let text = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    expected_digest(&text, asset_name)
+        .with_context(|| format!("{} is not listed in {}", asset_name, sums.name))
+}
+
+/// Получает файл кандидата в `path` — скачивает (с докачкой и повторами, см. download_file_async)
+/// или копирует из офлайн-набора — и возвращает его SHA-256 и размер; размер сверяется с Asset::size.
+/// Если ожидаемая сумма известна и не совпала — удаляет файл и возвращает ошибку.
+async fn fetch_verified_async(
+    source: &AssetSource<'_>,
+    candidate: &ReleaseCandidate,
+    path: &Path,
+) -> anyhow::Result<(String, u64)> {
+    let size = match source {
+        AssetSource::Download(client) => {
+            download_file_async(
+                client,
+                &candidate.url,
+                &HeaderMap::new(),
+                path,
+                candidate.size,
+                None,
+            )
+            .await?
+        }
+        AssetSource::Local(file) => {
+            let size = fs::copy(file, path)
+                .await
+                .with_context(|| format!("Failed to copy {}", file.display()))?;
+            if let Some(expected) = candidate.size
+                && expected != size
+            {
+                let _ = fs::remove_file(path).await;
+                bail!(
+                    "size mismatch for {}: expected {} bytes, got {}",
+                    candidate.asset_name,
+                    expected,
+                    size
+                );
+            }
+            size
+        }
+    };
+
+    let owned = path.to_path_buf();
+    let actual = tokio::task::spawn_blocking(move || sha256_file(&owned)).await??;
+    match &candidate.sha256 {
+        Some(expected) if !actual.eq_ignore_ascii_case(expected) => {
+            let _ = fs::remove_file(path).await;
+            bail!(
+                "checksum mismatch for {}: expected {}, got {}",
+                candidate.asset_name,
+                expected,
+                actual
+            )
+        }
+        Some(_) => {}
+        None => eprintln!(
+            "Warning: {} installed without checksum verification",
+            candidate.asset_name
+        ),
+    }
+    Ok((actual, size))
+}
+
+/// Жёсткая ссылка `src` -> `dst`, если файловая система их не поддерживает — копия.
+async fn link_or_copy(src: &Path, dst: &Path) -> std::io::Result<()> {
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    // dst может оказаться ссылкой на тот же файл — copy поверх неё обнулил бы оригинал
+    let _ = fs::remove_file(dst).await;
+    if fs::hard_link(src, dst).await.is_err() {
+        fs::copy(src, dst).await?;
+    }
+    Ok(())
+}
+
+/// Возвращает `bin` в состояние до swap_into_bin: установленные файлы `installed` уходят обратно
+/// в `staged`, на их место из `backup` возвращаются прежние версии.
+async fn restore_bin(bin: &Path, staged: &Path, backup: &Path, installed: &[&String]) {
+    for rel in installed.iter().rev() {
+        let current = bin.join(rel);
+        let _ = link_or_copy(&current, &staged.join(rel)).await;
+        let old = backup.join(rel);
+        let restored = if fs::symlink_metadata(&old).await.is_ok() {
+            fs::rename(&old, &current).await
+        } else {
+            fs::remove_file(&current).await
+        };
+        if let Err(e) = restored {
+            eprintln!("Failed to restore {}: {}", current.display(), e);
+        }
+    }
+}
+
+/// Переносит файлы `new_files` из `staged` в `bin` (BIN_DIR).
+/// - текущие версии файлов (`old_files` и заменяемые) сначала сохраняются жёсткими ссылками
+///   (или копиями) в `bin`/.previous/<tool>, сами файлы в `bin` при этом не трогаются;
+/// - каждый новый файл заменяет старый одним rename, так что yt-dlp и ffmpeg в `bin` не пропадают
+///   ни на мгновение;
+/// - при ошибке уже заменённые файлы возвращаются к прежним версиям, а `staged` — в исходное
+///   состояние; прежняя .previous/<tool> остаётся нетронутой.
+async fn swap_into_bin(
+    bin: &Path,
+    name: &str,
+    staged: &Path,
+    new_files: &[String],
+    old_files: &[String],
+) -> anyhow::Result<()> {
+    let previous = bin.join(PREVIOUS_DIR).join(name);
+    let backup = bin.join(PREVIOUS_DIR).join(format!("{}.tmp", name));
+    let _ = fs::remove_dir_all(&backup).await;
+    fs::create_dir_all(&backup).await?;
+
+    let replaced: BTreeSet<&String> = old_files.iter().chain(new_files).collect();
+    for rel in replaced {
+        let current = bin.join(rel);
+        if fs::symlink_metadata(&current).await.is_ok()
+            && let Err(e) = link_or_copy(&current, &backup.join(rel)).await
+        {
+            let _ = fs::remove_dir_all(&backup).await;
+            return Err(
+                anyhow::Error::new(e).context(format!("Failed to back up {}", current.display()))
+            );
+        }
+    }
+
+    let mut installed = Vec::new();
+    for rel in new_files {
+        let target = bin.join(rel);
+        let result = async {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::rename(staged.join(rel), &target).await
+        }
+        .await;
+        if let Err(e) = result {
+            restore_bin(bin, staged, &backup, &installed).await;
+            let _ = fs::remove_dir_all(&backup).await;
+            return Err(
+                anyhow::Error::new(e).context(format!("Failed to install {}", target.display()))
+            );
+        }
+        installed.push(rel);
+    }
+
+    // файлы прежней версии, которых нет в новой, уже сохранены в резервной копии
+    for rel in old_files.iter().filter(|rel| !new_files.contains(rel)) {
+        let _ = fs::remove_file(bin.join(rel)).await;
+    }
+    let _ = fs::remove_dir_all(&previous).await;
+    fs::rename(&backup, &previous).await?;
+    Ok(())
+}
+
+/// Формат скачанного файла: ToolSpec::archive ("binary" — не архив) или по имени файла.
+fn archive_kind(spec: &ToolSpec, asset_name: &str) -> anyhow::Result<Option<ArchiveKind>> {
+    match spec.archive.as_deref() {
+        Some("binary") => Ok(None),
+        Some(kind) => ArchiveKind::from_name(kind)
+            .map(Some)
+            .with_context(|| format!("unknown archive type {}", kind)),
+        None => Ok(ArchiveKind::from_name(asset_name)),
+    }
+}
+
+/// Имя файла загрузки в BIN_DIR/.staging/<tool>: тег + asset, чтобы недокачанный
+/// "<имя>.part" другой версии не дописывался к новой.
+fn download_name(candidate: &ReleaseCandidate) -> String {
+    sanitize_file_name(&format!("{}-{}", candidate.tag, candidate.asset_name))
+}
+
+/// Очищает каталог подготовки инструмента, оставляя только "<download>.part"
+/// от прерванной загрузки той же версии — с него продолжится докачка.
+async fn clear_staging(staging: &Path, download: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(staging).await?;
+    let keep = download
+        .file_name()
+        .map(|name| format!("{}.part", name.to_string_lossy()));
+    let mut entries = fs::read_dir(staging).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if keep.as_deref() == Some(&*entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type().await?.is_dir() {
+            fs::remove_dir_all(&path).await?;
+        } else {
+            fs::remove_file(&path).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Скачивает, проверяет и устанавливает инструмент по описанию `spec` в BIN_DIR:
+/// - находит файл (find_release_async), докачивает его и сверяет SHA-256;
+/// - архив распаковывает (только записи из ToolSpec::extract), одиночный бинарник
+///   делает исполняемым — всё это в BIN_DIR/.staging;
+/// - подменяет файлы в BIN_DIR через rename, сохраняя прежнюю версию для отката;
+/// - записывает тег, asset, размер, хэш и время установки в tools.json.
+async fn install_tool_async(
+    client: &reqwest::Client,
+    spec: &ToolSpec,
+) -> anyhow::Result<ToolRecord> {
+    let pin = pinned_tag(&spec.name);
+    let candidate = find_release_async(client, spec, pin.as_deref()).await?;
+    install_candidate_async(AssetSource::Download(client), spec, candidate).await
+}
+
+/// Устанавливает выбранный файл `candidate` из `source`: проверка, распаковка в BIN_DIR/.staging,
+/// подмена файлов в BIN_DIR и запись в tools.json.
+pub(crate) async fn install_candidate_async(
+    source: AssetSource<'_>,
+    spec: &ToolSpec,
+    candidate: ReleaseCandidate,
+) -> anyhow::Result<ToolRecord> {
+    let _guard = INSTALL_LOCK.lock().await;
+    match source {
+        AssetSource::Download(_) => println!(
+            "downloading {} {} ({})",
+            spec.name, candidate.tag, candidate.asset_name
+        ),
+        AssetSource::Local(file) => println!(
+            "installing {} {} from {}",
+            spec.name,
+            candidate.tag,
+            file.display()
+        ),
+    }
+
+    let staging = Path::new(BIN_DIR).join(STAGING_DIR).join(&spec.name);
+    let download = staging.join(download_name(&candidate));
+    clear_staging(&staging, &download).await?;
+    let files_dir = staging.join("files");
+    fs::create_dir_all(&files_dir).await?;
+    let target = spec.target_name();
+
+    let (sha256, size) = fetch_verified_async(&source, &candidate, &download).await?;
+    let files: Vec<String> = match archive_kind(spec, &candidate.asset_name)? {
+        Some(kind) => {
+            let archive = download.clone();
+            let options = ExtractOptions {
+                include: spec
+                    .extract
+                    .iter()
+                    .map(|p| glob::Pattern::new(p))
+                    .collect::<Result<_, _>>()
+                    .context("invalid extract pattern")?,
+                flatten: !spec.extract.is_empty(),
+                ..ExtractOptions::default()
+            };
+            let dest = files_dir.clone();
+            let report = tokio::task::spawn_blocking(move || {
+                extract_archive(kind, &archive, &dest, &options)
+            })
+            .await?
+            .context("Ошибка при распаковке")?;
+            for skipped in &report.skipped {
+                eprintln!("Skipped {}: {:?}", skipped.name, skipped.reason);
+            }
+            report
+                .extracted
+                .iter()
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+                .collect()
+        }
+        None => {
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&download, std::fs::Permissions::from_mode(0o755)).await?;
+            }
+            fs::rename(&download, files_dir.join(&target)).await?;
+            vec![target.clone()]
+        }
+    };
+    if !files.contains(&target) {
+        return Err(anyhow!(
+            "{} does not contain {}",
+            candidate.asset_name,
+            target
+        ));
+    }
+
+    let old_files = ToolManifest::load()
+        .ok()
+        .and_then(|m| m.tools.get(&spec.name).map(|r| r.files.clone()))
+        .unwrap_or_default();
+    swap_into_bin(
+        Path::new(BIN_DIR),
+        &spec.name,
+        &files_dir,
+        &files,
+        &old_files,
+    )
+    .await?;
+    let _ = fs::remove_dir_all(&staging).await;
+
+    let record = ToolRecord {
+        tag: candidate.tag,
+        size: candidate.size.unwrap_or(size),
+        asset: candidate.asset_name,
+        sha256: candidate.upstream_sha256.unwrap_or(sha256),
+        bundle_sha256: None,
+        installed_at: chrono::Utc::now(),
+        files,
+    };
+    ToolManifest::install(&spec.name, record.clone())?;
+    println!("installed {} {}", spec.name, record.tag);
+    Ok(record)
+}
+
+/// Скачивает в BIN_DIR инструменты, которые не нашлись ни в BIN_DIR, ни в PATH:
+/// yt-dlp, ffmpeg и дополнительные из Config::extra_tools.
+/// Если задан Config::tool_bundle, недостающие инструменты сначала ставятся из офлайн-набора.
+/// Если для обязательного инструмента нет сборки под текущую платформу, выводит подсказку
+/// установить его вручную. Ошибки загрузки только печатаются: сервер всё равно запускается.
+pub async fn ensure_tools_async() {
+    for name in missing_tools() {
+        if find_spec(name).is_none() {
+            eprintln!(
+                "{} not found in {} or PATH and there is no prebuilt release for {}/{}; install it and add it to PATH",
+                name,
+                BIN_DIR,
+                std::env::consts::OS,
+                std::env::consts::ARCH
+            );
+        }
+    }
+
+    let bundle = Config::get_unwrap().tool_bundle.as_deref();
+    if let Some(bundle) = bundle
+        && tool_specs()
+            .iter()
+            .any(|spec| resolve(&spec.name).is_none())
+        && let Err(e) = install_bundle_async(Path::new(bundle), true).await
+    {
+        eprintln!("Tool bundle {} failed: {:#}", bundle, e);
+    }
+
+    let client = match http_client() {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("Tool download skipped: {:#}", e);
+            return;
+        }
+    };
+    for spec in tool_specs() {
+        if resolve(&spec.name).is_some() {
+            continue;
+        }
+        if let Err(e) = install_tool_async(client, &spec).await {
+            eprintln!("{} download failed: {:#}", spec.name, e);
+        }
+    }
+}
+
+/// Установлена ли уже сборка `candidate`: тот же тег и тот же SHA-256.
+/// Без суммы у кандидата (allow_unverified) сравнить можно только тег.
+fn is_current(installed: Option<&ToolRecord>, candidate: &ReleaseCandidate) -> bool {
+    installed.is_some_and(|r| {
+        r.tag == candidate.tag
+            && candidate
+                .sha256
+                .as_deref()
+                .is_none_or(|sha256| r.sha256.eq_ignore_ascii_case(sha256))
+    })
+}
+
+/// Проверяет источники и обновляет инструменты `specs`, если доступна другая сборка
+/// (другой тег или другой SHA-256 — у BtbN тег "latest" переиспользуется, см. is_current).
+/// Закреплённый в pinned_tools тег считается целевой версией.
+pub async fn update_tools_async(specs: &[ToolSpec]) -> Vec<UpdateOutcome> {
+    let client = http_client();
+    let manifest = ToolManifest::load().unwrap_or_default();
+    let mut outcomes = Vec::new();
+    for spec in specs {
+        let installed = manifest.tools.get(&spec.name);
+        let mut outcome = UpdateOutcome {
+            tool: spec.name.clone(),
+            status: UpdateStatus::Failed,
+            installed: installed
+                .map(|r| r.tag.clone())
+                .filter(|tag| !tag.is_empty()),
+            available: None,
+            message: None,
+        };
+
+        let pin = pinned_tag(&spec.name);
+        let client = match &client {
+            Ok(client) => *client,
+            Err(e) => {
+                outcome.message = Some(format!("{:#}", e));
+                outcomes.push(outcome);
+                continue;
+            }
+        };
+        let result = match find_release_async(client, spec, pin.as_deref()).await {
+            Ok(candidate) => {
+                outcome.available = Some(candidate.tag.clone()).filter(|tag| !tag.is_empty());
+                if is_current(installed, &candidate) && bundled_path(spec).is_file() {
+                    Ok(UpdateStatus::UpToDate)
+                } else {
+                    install_candidate_async(AssetSource::Download(client), spec, candidate)
+                        .await
+                        .map(|_| UpdateStatus::Updated)
+                }
+            }
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(status) => outcome.status = status,
+            Err(e) => outcome.message = Some(format!("{:#}", e)),
+        }
+        outcomes.push(outcome);
+    }
+    outcomes
+}
+
+/// Возвращает предыдущую версию инструмента `name` из BIN_DIR/.previous; текущая становится
+/// предыдущей, так что повторный откат возвращает обновление.
+pub async fn rollback_tool_async(name: &str) -> anyhow::Result<ToolRecord> {
+    let _guard = INSTALL_LOCK.lock().await;
+    let manifest = ToolManifest::load()?;
+    let previous = manifest
+        .previous
+        .get(name)
+        .with_context(|| format!("no previous version of {} to roll back to", name))?;
+    let current_files = manifest
+        .tools
+        .get(name)
+        .map(|r| r.files.clone())
+        .unwrap_or_default();
+
+    let bin = Path::new(BIN_DIR);
+    let staging = bin.join(STAGING_DIR).join(name);
+    let _ = fs::remove_dir_all(&staging).await;
+    fs::create_dir_all(bin.join(STAGING_DIR)).await?;
+    fs::rename(bin.join(PREVIOUS_DIR).join(name), &staging)
+        .await
+        .context("previous version files are missing")?;
+    if let Err(e) = swap_into_bin(bin, name, &staging, &previous.files, &current_files).await {
+        // swap_into_bin вернул staging в исходное состояние — это снова предыдущая версия
+        let _ = fs::rename(&staging, bin.join(PREVIOUS_DIR).join(name)).await;
+        return Err(e);
+    }
+    let _ = fs::remove_dir_all(&staging).await;
+
+    let record = ToolManifest::rollback(name)?;
+    println!("rolled back {} to {}", name, record.tag);
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(path: &Path, content: &str) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    fn read(path: &Path) -> String {
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    fn names(files: &[&str]) -> Vec<String> {
+        files.iter().map(|f| f.to_string()).collect()
+    }
+
+    fn candidate(tag: &str, sha256: Option<&str>) -> ReleaseCandidate {
+        ReleaseCandidate {
+            tag: tag.to_string(),
+            asset_name: "curl.zip".to_string(),
+            url: "https://curl.se/windows/latest.cgi".to_string(),
+            size: None,
+            sha256: sha256.map(str::to_string),
+            upstream_sha256: None,
+        }
+    }
+
+    #[test]
+    fn current_build_is_compared_by_tag_without_checksum() {
+        let record = ToolRecord {
+            tag: "8.10.1".to_string(),
+            sha256: "ab".repeat(32),
+            ..ToolRecord::default()
+        };
+        let installed = Some(&record);
+        assert!(is_current(installed, &candidate("8.10.1", None)));
+        assert!(!is_current(installed, &candidate("8.11.0", None)));
+        assert!(is_current(
+            installed,
+            &candidate("8.10.1", Some(&"AB".repeat(32)))
+        ));
+        assert!(!is_current(
+            installed,
+            &candidate("8.10.1", Some(&"cd".repeat(32)))
+        ));
+        assert!(!is_current(None, &candidate("8.10.1", None)));
+    }
+
+    #[tokio::test]
+    async fn swap_replaces_files_and_keeps_previous_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let (bin, staged) = (dir.path().join("bin"), dir.path().join("staged"));
+        write(&bin.join("ffmpeg"), "old ffmpeg");
+        write(&bin.join("old.dll"), "old dll");
+        write(
+            &bin.join(PREVIOUS_DIR).join("ffmpeg/ffmpeg"),
+            "older ffmpeg",
+        );
+        write(&staged.join("ffmpeg"), "new ffmpeg");
+        write(&staged.join("ffprobe"), "new ffprobe");
+
+        let new_files = names(&["ffmpeg", "ffprobe"]);
+        swap_into_bin(
+            &bin,
+            "ffmpeg",
+            &staged,
+            &new_files,
+            &names(&["ffmpeg", "old.dll"]),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(read(&bin.join("ffmpeg")), "new ffmpeg");
+        assert_eq!(read(&bin.join("ffprobe")), "new ffprobe");
+        assert!(!bin.join("old.dll").exists());
+        let previous = bin.join(PREVIOUS_DIR).join("ffmpeg");
+        assert_eq!(read(&previous.join("ffmpeg")), "old ffmpeg");
+        assert_eq!(read(&previous.join("old.dll")), "old dll");
+        assert!(!previous.join("ffprobe").exists());
+    }
+
+    #[tokio::test]
+    async fn failed_swap_restores_bin_staging_and_previous() {
+        let dir = tempfile::tempdir().unwrap();
+        let (bin, staged) = (dir.path().join("bin"), dir.path().join("staged"));
+        write(&bin.join("ffmpeg"), "old ffmpeg");
+        write(&bin.join("ffprobe"), "old ffprobe");
+        write(
+            &bin.join(PREVIOUS_DIR).join("ffmpeg/ffmpeg"),
+            "older ffmpeg",
+        );
+        write(&staged.join("ffmpeg"), "new ffmpeg");
+        // "ffprobe" не подготовлен — вторая замена упадёт
+
+        let files = names(&["ffmpeg", "ffprobe"]);
+        let result = swap_into_bin(&bin, "ffmpeg", &staged, &files, &files).await;
+
+        assert!(result.is_err());
+        assert_eq!(read(&bin.join("ffmpeg")), "old ffmpeg");
+        assert_eq!(read(&bin.join("ffprobe")), "old ffprobe");
+        assert_eq!(read(&staged.join("ffmpeg")), "new ffmpeg");
+        let previous = bin.join(PREVIOUS_DIR);
+        assert_eq!(read(&previous.join("ffmpeg/ffmpeg")), "older ffmpeg");
+        assert!(!previous.join("ffmpeg.tmp").exists());
+    }
+}
@@ -0,0 +1,41 @@
+use crate::tool_spec::{REQUIRED_TOOLS, ToolSpec, exe_name, find_spec};
+use std::path::{Path, PathBuf};
+
+/// Каталог, куда скачиваются инструменты при первом запуске
+pub const BIN_DIR: &str = "bin_";
+
+/// Путь, по которому инструмент лежит (или будет лежать после загрузки) в BIN_DIR.
+pub fn bundled_path(spec: &ToolSpec) -> PathBuf {
+    Path::new(BIN_DIR).join(spec.target_name())
+}
+
+/// Ищет исполняемый файл в каталогах из переменной PATH.
+fn find_in_path(exe_name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(exe_name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Находит инструмент `name`: сначала в BIN_DIR, затем в PATH.
+/// Имя файла берётся из описания инструмента (ToolSpec::target_name), а если описания
+/// для текущей платформы нет — name + расширение ОС.
+/// Возвращает None, если его нет ни там, ни там.
+pub fn resolve(name: &str) -> Option<PathBuf> {
+    let exe = find_spec(name)
+        .map(|spec| spec.target_name())
+        .unwrap_or_else(|| exe_name(name));
+    let bundled = Path::new(BIN_DIR).join(&exe);
+    if bundled.is_file() {
+        return Some(bundled);
+    }
+    find_in_path(&exe)
+}
+
+/// Обязательные инструменты (yt-dlp, ffmpeg), которые не удалось найти через resolve().
+pub fn missing_tools() -> Vec<&'static str> {
+    REQUIRED_TOOLS
+        .into_iter()
+        .filter(|name| resolve(name).is_none())
+        .collect()
+}
@@ -0,0 +1,101 @@
+use crate::tool_locator::BIN_DIR;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Манифест установленных инструментов, лежит в BIN_DIR
+pub const MANIFEST_FILE: &str = "tools.json";
+
+/// Запись об установленном инструменте.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolRecord {
+    /// Тег релиза GitHub
+    pub tag: String,
+    /// Имя скачанного asset'а релиза
+    pub asset: String,
+    /// Размер asset'а в байтах
+    pub size: u64,
+    /// Проверенный SHA-256 asset'а (hex). Для перепакованного файла офлайн-набора — SHA-256
+    /// исходного asset'а релиза, чтобы update_tools_async сравнивал с ним
+    pub sha256: String,
+    /// SHA-256 перепакованного файла `asset` в офлайн-наборе (только в tools.json набора)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bundle_sha256: Option<String>,
+    pub installed_at: DateTime<Utc>,
+    /// Файлы, установленные в BIN_DIR (пути относительно него)
+    pub files: Vec<String>,
+}
+
+/// Содержимое tools.json: имя инструмента → запись об установке.
+/// `previous` хранит предыдущую версию каждого инструмента для отката.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ToolManifest {
+    pub tools: BTreeMap<String, ToolRecord>,
+    #[serde(default)]
+    pub previous: BTreeMap<String, ToolRecord>,
+}
+
+impl ToolManifest {
+    pub fn path() -> PathBuf {
+        Path::new(BIN_DIR).join(MANIFEST_FILE)
+    }
+
+    /// Загружает манифест; если файла нет — возвращает пустой.
+    pub fn load() -> anyhow::Result<Self> {
+        let path = Self::path();
+        if !path.exists() {
+            return Ok(ToolManifest::default());
+        }
+        Self::load_from(&path)
+    }
+
+    /// Читает манифест из произвольного файла (например, из офлайн-набора).
+    pub fn load_from(path: &Path) -> anyhow::Result<Self> {
+        let json = fs::read_to_string(path).context("Failed to read tool manifest")?;
+        serde_json::from_str(&json).context("Failed to parse tool manifest")
+    }
+
+    /// Сохраняет манифест в BIN_DIR.
+    pub fn save(&self) -> anyhow::Result<()> {
+        fs::create_dir_all(BIN_DIR)?;
+        self.save_to(&Self::path())
+    }
+
+    /// Сохраняет манифест через временный файл, чтобы при сбое не остался обрезанный tools.json.
+    pub fn save_to(&self, path: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json).context("Failed to write tool manifest")?;
+        fs::rename(&tmp, path).context("Failed to write tool manifest")?;
+        Ok(())
+    }
+
+    /// Записывает установку новой версии `tool`; прежняя запись становится `previous`.
+    pub fn install(tool: &str, record: ToolRecord) -> anyhow::Result<()> {
+        let mut manifest = Self::load().unwrap_or_default();
+        match manifest.tools.insert(tool.to_string(), record) {
+            Some(old) => manifest.previous.insert(tool.to_string(), old),
+            None => manifest.previous.remove(tool),
+        };
+        manifest.save()
+    }
+
+    /// Меняет местами текущую и предыдущую запись `tool` после отката.
+    /// Возвращает запись, ставшую текущей.
+    pub fn rollback(tool: &str) -> anyhow::Result<ToolRecord> {
+        let mut manifest = Self::load()?;
+        let previous = manifest
+            .previous
+            .remove(tool)
+            .with_context(|| format!("no previous version of {} to roll back to", tool))?;
+        if let Some(current) = manifest.tools.insert(tool.to_string(), previous.clone()) {
+            manifest.previous.insert(tool.to_string(), current);
+        }
+        manifest.save()?;
+        Ok(previous)
+    }
+}
@@ -0,0 +1,238 @@
+use crate::config_manager::Config;
+use serde::{Deserialize, Serialize};
+use std::env::consts::{ARCH, EXE_SUFFIX, OS};
+
+/// Имена инструментов, без которых конвейер не работает
+pub const YTDLP: &str = "yt-dlp";
+pub const FFMPEG: &str = "ffmpeg";
+pub const CURL: &str = "curl";
+pub const REQUIRED_TOOLS: [&str; 2] = [YTDLP, FFMPEG];
+
+/// Откуда скачивать инструмент.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ToolSource {
+    /// Релизы GitHub-репозитория вида "owner/name"
+    Github { repo: String },
+    /// Прямая ссылка на бинарник или архив
+    Url { url: String },
+}
+
+/// Правило выбора asset'а релиза для платформы: регулярное выражение имени
+/// и ОС/архитектура в терминах std::env::consts ("linux", "windows", "macos"; "x86_64", "aarch64").
+/// Не заданные os/arch подходят для любой платформы.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssetRule {
+    pub pattern: String,
+    #[serde(default)]
+    pub os: Option<String>,
+    #[serde(default)]
+    pub arch: Option<String>,
+}
+
+impl AssetRule {
+    /// Подходит ли правило для ОС `os` и архитектуры `arch`.
+    pub fn matches(&self, os: &str, arch: &str) -> bool {
+        self.os
+            .as_deref()
+            .is_none_or(|rule| rule.eq_ignore_ascii_case(os))
+            && self
+                .arch
+                .as_deref()
+                .is_none_or(|rule| rule.eq_ignore_ascii_case(arch))
+    }
+}
+
+/// Декларативное описание инструмента: откуда его брать и что положить в BIN_DIR.
+/// Встроенные описания (yt-dlp, ffmpeg) дополняются Config::extra_tools.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolSpec {
+    pub name: String,
+    pub source: ToolSource,
+    /// Регулярное выражение для имени asset'а релиза (для Github); самый крупный подходящий asset выигрывает
+    #[serde(default)]
+    pub asset: Option<String>,
+    /// Правила выбора asset'а по ОС/архитектуре, если `asset` не задан; берётся первое подходящее
+    #[serde(default)]
+    pub assets: Vec<AssetRule>,
+    /// Рассматривать prerelease-релизы (nightly и т.п.); по умолчанию только стабильные
+    #[serde(default)]
+    pub include_prereleases: bool,
+    /// "zip", "tar.xz", "tar.gz", "tar" или "binary"; по умолчанию определяется по имени файла
+    #[serde(default)]
+    pub archive: Option<String>,
+    /// Glob-шаблоны путей внутри архива, которые нужно извлечь (например "*/bin/*");
+    /// найденные файлы кладутся прямо в BIN_DIR без каталогов. Пусто — извлечь всё
+    #[serde(default)]
+    pub extract: Vec<String>,
+    /// Имя исполняемого файла в BIN_DIR; по умолчанию name + расширение ОС
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Ожидаемый SHA-256 asset'а; если не задан, ищется файл сумм в релизе
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// Разрешить установку без контрольной суммы, если источник её не публикует
+    #[serde(default)]
+    pub allow_unverified: bool,
+}
+
+impl ToolSpec {
+    /// Имя исполняемого файла в BIN_DIR / PATH.
+    pub fn target_name(&self) -> String {
+        self.target.clone().unwrap_or_else(|| exe_name(&self.name))
+    }
+
+    /// Регулярное выражение asset'а для текущей платформы: `asset` или первое подходящее правило из `assets`.
+    pub fn asset_pattern(&self) -> Option<&str> {
+        self.asset_pattern_for(OS, ARCH)
+    }
+
+    /// То же, что asset_pattern, для ОС `os` и архитектуры `arch`.
+    pub fn asset_pattern_for(&self, os: &str, arch: &str) -> Option<&str> {
+        self.asset.as_deref().or_else(|| {
+            self.assets
+                .iter()
+                .find(|rule| rule.matches(os, arch))
+                .map(|rule| rule.pattern.as_str())
+        })
+    }
+
+    /// Имя и целевой файл из конфига становятся путями внутри BIN_DIR —
+    /// разрешены только простые имена файлов.
+    pub fn validate(&self) -> Result<(), String> {
+        if !is_plain_file_name(&self.name) {
+            return Err(format!("invalid tool name {:?}", self.name));
+        }
+        if !is_plain_file_name(&self.target_name()) {
+            return Err(format!(
+                "invalid target {:?} for {}",
+                self.target_name(),
+                self.name
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Простое имя файла без каталогов: его можно безопасно присоединить к BIN_DIR.
+pub fn is_plain_file_name(s: &str) -> bool {
+    !s.is_empty() && s != "." && s != ".." && !s.contains(['/', '\\', ':', '\0'])
+}
+
+/// `s` как безопасное имя файла: всё, кроме ASCII-букв, цифр, '.', '-' и '_', заменяется на '_'.
+pub fn sanitize_file_name(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Имя исполняемого файла на текущей ОС ("yt-dlp.exe" на Windows, "yt-dlp" в остальных).
+pub fn exe_name(name: &str) -> String {
+    format!("{}{}", name, EXE_SUFFIX)
+}
+
+fn github(name: &str, repo: &str, asset: &str, extract: &[&str]) -> ToolSpec {
+    ToolSpec {
+        name: name.to_string(),
+        source: ToolSource::Github {
+            repo: repo.to_string(),
+        },
+        asset: Some(format!("^{}$", regex::escape(asset))),
+        assets: Vec::new(),
+        include_prereleases: false,
+        archive: None,
+        extract: extract.iter().map(|s| s.to_string()).collect(),
+        target: None,
+        sha256: None,
+        allow_unverified: false,
+    }
+}
+
+/// Архив по прямой ссылке без опубликованной контрольной суммы.
+fn unverified_zip(name: &str, url: &str, extract: &[&str]) -> ToolSpec {
+    ToolSpec {
+        name: name.to_string(),
+        source: ToolSource::Url {
+            url: url.to_string(),
+        },
+        asset: None,
+        assets: Vec::new(),
+        include_prereleases: false,
+        archive: Some("zip".to_string()),
+        extract: extract.iter().map(|s| s.to_string()).collect(),
+        target: None,
+        sha256: None,
+        allow_unverified: true,
+    }
+}
+
+/// Встроенные описания yt-dlp, ffmpeg и (на Windows) curl для текущих ОС и архитектуры.
+/// Для платформ без готовой сборки (например, ffmpeg на macOS) описания нет —
+/// такой инструмент нужно поставить самостоятельно и добавить в PATH.
+pub fn builtin_specs() -> Vec<ToolSpec> {
+    let ytdlp = match (OS, ARCH) {
+        ("windows", "x86_64") => Some("yt-dlp.exe"),
+        ("windows", "x86") => Some("yt-dlp_x86.exe"),
+        ("windows", "aarch64") => Some("yt-dlp_arm64.exe"),
+        ("linux", "x86_64") => Some("yt-dlp_linux"),
+        ("linux", "aarch64") => Some("yt-dlp_linux_aarch64"),
+        ("macos", _) => Some("yt-dlp_macos"),
+        _ => None,
+    };
+    let ffmpeg = match (OS, ARCH) {
+        ("windows", "x86_64") => Some("ffmpeg-master-latest-win64-lgpl-shared.zip"),
+        ("windows", "aarch64") => Some("ffmpeg-master-latest-winarm64-lgpl-shared.zip"),
+        // на Linux берём статические сборки: shared-варианту нужен ещё каталог lib/
+        ("linux", "x86_64") => Some("ffmpeg-master-latest-linux64-lgpl.tar.xz"),
+        ("linux", "aarch64") => Some("ffmpeg-master-latest-linuxarm64-lgpl.tar.xz"),
+        _ => None,
+    };
+    // curl.se отдаёт по ссылке "latest" каждый раз новую сборку и не публикует для неё суммы,
+    // поэтому закрепить SHA-256 нельзя; для проверенной установки curl можно описать в extra_tools
+    let curl = match (OS, ARCH) {
+        ("windows", "x86_64") => Some("win64-mingw"),
+        ("windows", "aarch64") => Some("win64a-mingw"),
+        ("windows", "x86") => Some("win32-mingw"),
+        _ => None,
+    };
+
+    let mut specs = Vec::new();
+    if let Some(asset) = ytdlp {
+        specs.push(github(YTDLP, "yt-dlp/yt-dlp", asset, &[]));
+    }
+    if let Some(asset) = ffmpeg {
+        specs.push(github(FFMPEG, "BtbN/FFmpeg-Builds", asset, &["*/bin/*"]));
+    }
+    if let Some(build) = curl {
+        let url = format!("https://curl.se/windows/latest.cgi?p={}.zip", build);
+        specs.push(unverified_zip(CURL, &url, &["*/bin/curl.exe"]));
+    }
+    specs
+}
+
+/// Все инструменты: встроенные и из Config::extra_tools
+/// (описание из конфига с тем же именем заменяет встроенное).
+pub fn tool_specs() -> Vec<ToolSpec> {
+    let extra = Config::get()
+        .map(|config| config.extra_tools.clone())
+        .unwrap_or_default();
+    let mut specs: Vec<ToolSpec> = builtin_specs()
+        .into_iter()
+        .filter(|spec| !extra.iter().any(|e| e.name == spec.name))
+        .collect();
+    specs.extend(extra);
+    specs
+}
+
+/// Описание инструмента по имени (без учёта регистра).
+pub fn find_spec(name: &str) -> Option<ToolSpec> {
+    tool_specs()
+        .into_iter()
+        .find(|spec| spec.name.eq_ignore_ascii_case(name))
+}
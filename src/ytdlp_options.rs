@@ -0,0 +1,350 @@
+use crate::config_manager::Config;
+use crate::loudness::{LoudnessMode, TARGET_LUFS_RANGE};
+use crate::structures::download_request::{DownloadOptions, FieldError};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::path::Path;
+
+/// Значения --audio-format, которые принимает сервер. "best" из yt-dlp не поддерживается:
+/// с ним расширение итогового файла зависит от источника, и сервер не знает, где его искать.
+pub const AUDIO_FORMATS: &[&str] = &["aac", "alac", "flac", "m4a", "mp3", "opus", "vorbis", "wav"];
+
+/// Расширение итогового файла и кодер ffmpeg для каждого --audio-format
+/// (как в FFmpegExtractAudioPP yt-dlp).
+const AUDIO_CODECS: &[(&str, &str, &str)] = &[
+    ("aac", "m4a", "aac"),
+    ("alac", "m4a", "alac"),
+    ("flac", "flac", "flac"),
+    ("m4a", "m4a", "aac"),
+    ("mp3", "mp3", "libmp3lame"),
+    ("opus", "opus", "libopus"),
+    ("vorbis", "ogg", "libvorbis"),
+    ("wav", "wav", "pcm_s16le"),
+];
+
+/// Расширение файла, который yt-dlp создаёт для `audio_format` ("vorbis" -> "ogg").
+pub fn audio_extension(audio_format: &str) -> &str {
+    AUDIO_CODECS
+        .iter()
+        .find(|(format, _, _)| *format == audio_format)
+        .map_or(audio_format, |(_, ext, _)| ext)
+}
+
+/// Кодер ffmpeg, нужный для `audio_format`.
+pub fn audio_encoder(audio_format: &str) -> Option<&'static str> {
+    AUDIO_CODECS
+        .iter()
+        .find(|(format, _, _)| *format == audio_format)
+        .map(|(_, _, encoder)| *encoder)
+}
+
+/// Опции, которые позволяют выполнить произвольную команду, подменить конфигурацию
+/// или читать/писать файлы вне каталога загрузок. Отклоняются с отдельным сообщением.
+/// Все они принимают значение.
+const DANGEROUS_FLAGS: &[&str] = &[
+    "--exec",
+    "--exec-before-download",
+    "--config-location",
+    "--config-locations",
+    "--ffmpeg-location",
+    "--plugin-dirs",
+    "-a",
+    "--batch-file",
+    "--load-info-json",
+    "-P",
+    "--paths",
+    "--cookies",
+    "--cookies-from-browser",
+    "--netrc-location",
+    "--netrc-cmd",
+    "--postprocessor-args",
+    "--ppa",
+    "--downloader",
+    "--external-downloader",
+    "--downloader-args",
+    "--external-downloader-args",
+    "--use-postprocessor",
+    "--download-archive",
+    "--print-to-file",
+    "--cache-dir",
+];
+
+/// Селектор формата: без пробелов и не начинается с '-'
+static FORMAT_SELECTOR: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^[\w+\[\]<>=*.!?:^$,()/][\w+\-\[\]<>=*.!?:^$,()/]*$").unwrap());
+
+/// Качество аудио: 0..10 (VBR) или битрейт вида "192K"
+static AUDIO_QUALITY: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?:10|[0-9]|[0-9]{2,3}[kK])$").unwrap());
+
+/// Преобразует аргументы yt-dlp из команды старого формата в типизированные опции.
+/// Разрешены только: -x/--extract-audio, --audio-format, --audio-quality, -f/--format,
+/// --embed-thumbnail, --add-metadata/--embed-metadata, -o/--output и http(s)-ссылка на трек.
+/// Любая другая опция, в том числе из DANGEROUS_FLAGS, отклоняется.
+/// Возвращает опции и найденную ссылку на трек.
+pub fn options_from_legacy_args(
+    args: Vec<String>,
+) -> Result<(DownloadOptions, Option<String>), Vec<FieldError>> {
+    let mut options = DownloadOptions {
+        embed_metadata: false,
+        ..DownloadOptions::default()
+    };
+    let mut media_url = None;
+    let mut errors = Vec::new();
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // "--opt=value" эквивалентно "--opt value"
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| {
+            inline_value.clone().or_else(|| args.next()).ok_or_else(|| {
+                FieldError::new("yt-dlp", format!("option {} requires a value", name))
+            })
+        };
+
+        match flag.as_str() {
+            "-x" | "--extract-audio" => {}
+            "--embed-thumbnail" => options.embed_thumbnail = true,
+            "--add-metadata" | "--embed-metadata" => options.embed_metadata = true,
+            "--audio-format" => match value(&flag) {
+                Ok(v) => options.audio_format = v,
+                Err(e) => errors.push(e),
+            },
+            "--audio-quality" => match value(&flag) {
+                Ok(v) => options.audio_quality = Some(v),
+                Err(e) => errors.push(e),
+            },
+            "-f" | "--format" => match value(&flag) {
+                Ok(v) => options.format = Some(v),
+                Err(e) => errors.push(e),
+            },
+            "-o" | "--output" => match value(&flag) {
+                Ok(v) => options.output = Some(v),
+                Err(e) => errors.push(e),
+            },
+            url if url.starts_with("http://") || url.starts_with("https://") => {
+                media_url = Some(arg);
+            }
+            flag if DANGEROUS_FLAGS.contains(&flag) => {
+                // все опции из списка принимают значение — пропускаем его вместе с опцией
+                let _ = value(flag);
+                errors.push(FieldError::new(
+                    "yt-dlp",
+                    format!("option {} is not allowed", flag),
+                ));
+            }
+            flag => {
+                errors.push(FieldError::new(
+                    "yt-dlp",
+                    format!("unknown or unsupported option {}", flag),
+                ));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok((options, media_url))
+    } else {
+        Err(errors)
+    }
+}
+
+/// Проверяет опции скачивания и дописывает найденные ошибки в `errors`.
+pub fn validate_options(options: &DownloadOptions, errors: &mut Vec<FieldError>) {
+    if !AUDIO_FORMATS.contains(&options.audio_format.as_str()) {
+        errors.push(FieldError::new(
+            "options.audio_format",
+            format!("must be one of: {}", AUDIO_FORMATS.join(", ")),
+        ));
+    }
+    if let Some(format) = &options.format
+        && !FORMAT_SELECTOR.is_match(format)
+    {
+        errors.push(FieldError::new(
+            "options.format",
+            "must be a yt-dlp format selector without spaces",
+        ));
+    }
+    if let Some(quality) = &options.audio_quality
+        && !AUDIO_QUALITY.is_match(quality)
+    {
+        errors.push(FieldError::new(
+            "options.audio_quality",
+            "must be 0-10 or a bitrate like 192K",
+        ));
+    }
+    if let Some(output) = &options.output {
+        if let Err(message) = check_output(output) {
+            errors.push(FieldError::new("options.output", message));
+        } else if AUDIO_FORMATS.contains(&options.audio_format.as_str()) {
+            // yt-dlp заменит расширение на своё (в нижнем регистре), и файл окажется не там,
+            // где его ждёт сервер: на регистрозависимой ФС ".OGG" — уже другой файл
+            let ext = audio_extension(&options.audio_format);
+            let matches = Path::new(output).extension().is_some_and(|e| e == ext);
+            if !matches {
+                errors.push(FieldError::new(
+                    "options.output",
+                    format!(
+                        "must end with .{} for audio_format {:?}",
+                        ext, options.audio_format
+                    ),
+                ));
+            }
+        }
+    }
+    // целевая громкость нужна только для normalize
+    let (min_lufs, max_lufs) = TARGET_LUFS_RANGE;
+    if options.loudness == LoudnessMode::Normalize
+        && !(min_lufs..=max_lufs).contains(&options.target_lufs)
+    {
+        errors.push(FieldError::new(
+            "options.target_lufs",
+            format!("must be between {} and {} LUFS", min_lufs, max_lufs),
+        ));
+    }
+}
+
+/// Путь вывода должен быть относительным, без '..' и без полей шаблона yt-dlp,
+/// чтобы сервер заранее знал, где окажется файл.
+fn check_output(output: &str) -> Result<(), &'static str> {
+    let output = output.trim();
+    if output.is_empty() {
+        return Err("must not be empty");
+    }
+    let bytes = output.as_bytes();
+    if output.starts_with(['/', '\\', '~'])
+        || (bytes.len() > 1 && bytes[1] == b':' && bytes[0].is_ascii_alphabetic())
+    {
+        return Err("must be a path relative to the download folder");
+    }
+    if output.starts_with('-') {
+        return Err("must not start with '-'");
+    }
+    if output.split(['/', '\\']).any(|part| part == "..") {
+        return Err("must not contain '..' segments");
+    }
+    if output.contains('%') {
+        return Err("yt-dlp template fields (%(...)s) are not supported");
+    }
+    Ok(())
+}
+
+/// Сетевые опции yt-dlp из конфига сервера: прокси, таймаут сокета и cookies,
+/// чтобы yt-dlp ходил в сеть так же, как загрузчик инструментов и обложек.
+/// Клиенту эти опции недоступны (см. DANGEROUS_FLAGS).
+pub fn network_args(config: &Config) -> Vec<String> {
+    let mut args = vec![
+        "--socket-timeout".to_string(),
+        config.read_timeout_secs.to_string(),
+    ];
+    if let Some(proxy) = &config.proxy {
+        args.extend(["--proxy".into(), proxy.clone()]);
+    }
+    if let Some(cookies) = &config.cookies_file {
+        args.extend(["--cookies".into(), cookies.clone()]);
+    }
+    if let Some(browser) = &config.cookies_from_browser {
+        args.extend(["--cookies-from-browser".into(), browser.clone()]);
+    }
+    args
+}
+
+/// Собирает командную строку yt-dlp из проверенных опций.
+/// --ignore-config не даёт подмешать пользовательский конфиг yt-dlp,
+/// "--" отделяет ссылку на трек от опций, --ffmpeg-location указывает на найденный ffmpeg,
+/// `network` — опции из network_args.
+pub fn build_ytdlp_args(
+    options: &DownloadOptions,
+    ffmpeg: &Path,
+    network: &[String],
+    out_path: &str,
+    media_url: &str,
+) -> Vec<String> {
+    let mut args: Vec<String> = vec![
+        "--ignore-config".into(),
+        "--ffmpeg-location".into(),
+        ffmpeg.to_string_lossy().into_owned(),
+    ];
+    args.extend_from_slice(network);
+    args.extend([
+        "-x".into(),
+        "--audio-format".into(),
+        options.audio_format.clone(),
+    ]);
+    if let Some(quality) = &options.audio_quality {
+        args.extend(["--audio-quality".into(), quality.clone()]);
+    }
+    if let Some(format) = &options.format {
+        args.extend(["-f".into(), format.clone()]);
+    }
+    if options.embed_thumbnail {
+        args.push("--embed-thumbnail".into());
+    }
+    if options.embed_metadata {
+        args.push("--embed-metadata".into());
+    }
+    args.extend(["-o".into(), out_path.to_string()]);
+    args.extend(["--".into(), media_url.to_string()]);
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(options: &DownloadOptions) -> Vec<String> {
+        let mut errors = Vec::new();
+        validate_options(options, &mut errors);
+        errors.into_iter().map(|e| e.field).collect()
+    }
+
+    /// Без DownloadOptions::default(): он читает config.json
+    fn options(audio_format: &str, output: Option<&str>) -> DownloadOptions {
+        DownloadOptions {
+            format: None,
+            audio_format: audio_format.to_string(),
+            audio_quality: None,
+            output: output.map(str::to_string),
+            embed_thumbnail: false,
+            embed_metadata: true,
+            loudness: Default::default(),
+            target_lufs: -14.0,
+        }
+    }
+
+    #[test]
+    fn best_audio_format_is_rejected() {
+        assert_eq!(errors(&options("best", None)), ["options.audio_format"]);
+    }
+
+    #[test]
+    fn output_extension_must_match_audio_format() {
+        assert!(errors(&options("vorbis", Some("Artist/Track.ogg"))).is_empty());
+        assert_eq!(
+            errors(&options("vorbis", Some("Artist/Track.OGG"))),
+            ["options.output"]
+        );
+        assert_eq!(
+            errors(&options("opus", Some("Artist/Track.mp3"))),
+            ["options.output"]
+        );
+        assert_eq!(
+            errors(&options("mp3", Some("Artist/Track"))),
+            ["options.output"]
+        );
+    }
+
+    #[test]
+    fn target_lufs_is_checked_only_for_normalize() {
+        let mut options = options("mp3", None);
+        options.target_lufs = 0.0;
+        assert!(errors(&options).is_empty());
+        options.loudness = LoudnessMode::Normalize;
+        assert_eq!(errors(&options), ["options.target_lufs"]);
+    }
+}
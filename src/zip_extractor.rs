@@ -1,64 +1,164 @@
+use crate::archive_extractor::{ExtractOptions, ExtractReport, Extraction, SkipReason};
 use std::fs::File;
+use std::io;
 use std::path::Path;
-use std::{fs, io};
 use zip::ZipArchive;
 
-fn ensure_parent(dir: &Path) -> io::Result<()> {
-    if let Some(parent) = dir.parent() {
-        fs::create_dir_all(parent)?;
-    }
-    Ok(())
-}
-
-/// Извлекает из zip-архива только файлы, путь которых начинается с `target_prefix`.
-/// `target_prefix` ожидается с forward-slashes и без ведущего слэша,
-/// например: "ffmpeg-master-latest-win64-lgpl-shared/bin/"
+/// Извлекает из zip-архива записи, отобранные `options`
+/// (префикс, strip-components, шаблоны include — см. ExtractOptions).
+///
+/// Имя каждой записи проверяется по семантике enclosed_name, symlink-записи пропускаются,
+/// а заявленный в заголовке коэффициент сжатия сверяется с ExtractLimits ещё до распаковки.
+/// При ошибке уже извлечённые файлы удаляются.
 pub fn extract_prefix_from_zip(
     zip_path: &Path,
     dest: &Path,
-    target_prefix: &str,
-) -> zip::result::ZipResult<()> {
+    options: &ExtractOptions,
+) -> zip::result::ZipResult<ExtractReport> {
     let file = File::open(zip_path)?;
     let mut archive = ZipArchive::new(file)?;
+    let mut extraction = Extraction::new(zip_path, dest, options)?;
+    let result = extract_entries(&mut archive, &mut extraction, options);
+    Ok(extraction.finish(result.map_err(io::Error::from))?)
+}
 
+fn extract_entries(
+    archive: &mut ZipArchive<File>,
+    extraction: &mut Extraction,
+    options: &ExtractOptions,
+) -> zip::result::ZipResult<()> {
     for i in 0..archive.len() {
         let mut entry = archive.by_index(i)?;
-        let name = entry.name();
+        extraction.count_entry()?;
+        let name = entry.name().to_string();
 
-        // Нормализуем: zip использует '/' в имени
-        if name.starts_with(target_prefix) {
-            // Получаем относительный путь внутри целевой папки (удаляем префикс)
-            let rel_path = &name[target_prefix.len()..];
+        if entry.enclosed_name().is_none() {
+            extraction.skip(&name, SkipReason::UnsafePath);
+            continue;
+        }
+        if entry.is_symlink() {
+            extraction.skip(&name, SkipReason::Link);
+            continue;
+        }
 
-            // Пропускаем пустые (например, если сам префикс указывает на директорию)
-            if rel_path.is_empty() {
-                continue;
-            }
+        // Получаем относительный путь внутри целевой папки (префикс и лишние компоненты отброшены)
+        let Some(rel_path) = extraction.select(&name, entry.is_dir()) else {
+            continue;
+        };
 
-            let out_path = dest.join(rel_path);
+        if entry.is_dir() {
+            extraction.create_dir(&rel_path)?;
+            continue;
+        }
 
-            if entry.is_dir() {
-                fs::create_dir_all(&out_path)?;
-            } else {
-                // Создаем родительские директории, если нужно
-                ensure_parent(&out_path).map_err(|e| zip::result::ZipError::Io(e))?;
+        let max_ratio = options.limits.max_ratio;
+        if entry.size() > entry.compressed_size().max(1).saturating_mul(max_ratio) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} expands more than {}x, refusing to extract",
+                    name, max_ratio
+                ),
+            )
+            .into());
+        }
+
+        let mode = entry.unix_mode();
+        extraction.write_file(&rel_path, &mut entry, mode)?;
+    }
+    Ok(())
+}
 
-                let mut outfile =
-                    File::create(&out_path).map_err(|e| zip::result::ZipError::Io(e))?;
-                io::copy(&mut entry, &mut outfile).map_err(|e| zip::result::ZipError::Io(e))?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
 
-                // Сохраняем unix-пермиссии, если есть (опционально)
-                #[cfg(unix)]
-                {
-                    use std::os::unix::fs::PermissionsExt;
-                    if let Some(mode) = entry.unix_mode() {
-                        fs::set_permissions(&out_path, fs::Permissions::from_mode(mode))
-                            .map_err(|e| zip::result::ZipError::Io(e))?;
-                    }
-                }
+    fn write_zip(path: &Path, files: &[(&str, &[u8])]) {
+        let mut zip = zip::ZipWriter::new(File::create(path).unwrap());
+        for (name, data) in files {
+            if name.ends_with('/') {
+                zip.add_directory(*name, SimpleFileOptions::default())
+                    .unwrap();
+            } else {
+                zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+                zip.write_all(data).unwrap();
             }
         }
+        zip.finish().unwrap();
     }
 
-    Ok(())
+    #[test]
+    fn skips_parent_and_absolute_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let (archive, dest) = (dir.path().join("tool.zip"), dir.path().join("dest"));
+        write_zip(
+            &archive,
+            &[
+                ("../evil", b"evil"),
+                ("/abs", b"evil"),
+                ("ffmpeg.exe", b"ffmpeg"),
+            ],
+        );
+
+        let report = extract_prefix_from_zip(&archive, &dest, &ExtractOptions::default()).unwrap();
+        assert_eq!(report.extracted, [Path::new("ffmpeg.exe")]);
+        let skipped: Vec<_> = report
+            .skipped
+            .iter()
+            .map(|s| (s.name.as_str(), s.reason))
+            .collect();
+        assert_eq!(
+            skipped,
+            [
+                ("../evil", SkipReason::UnsafePath),
+                ("/abs", SkipReason::UnsafePath)
+            ]
+        );
+        assert!(!dir.path().join("evil").exists());
+        assert!(!Path::new("/abs").exists());
+        let names: Vec<_> = fs::read_dir(&dest)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["ffmpeg.exe"]);
+    }
+
+    #[test]
+    fn nested_prefix_keeps_relative_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let (archive, dest) = (dir.path().join("tool.zip"), dir.path().join("dest"));
+        write_zip(
+            &archive,
+            &[
+                ("release/win64/", b""),
+                ("release/win64/bin/ffmpeg.exe", b"ffmpeg"),
+                ("release/win64/bin/presets/x264.ffpreset", b"preset"),
+                ("release/win32/bin/ffmpeg.exe", b"other"),
+                ("README.txt", b"readme"),
+            ],
+        );
+        let options = ExtractOptions {
+            prefix: "release/win64/".to_string(),
+            ..ExtractOptions::default()
+        };
+
+        let report = extract_prefix_from_zip(&archive, &dest, &options).unwrap();
+        assert_eq!(
+            report.extracted,
+            [
+                Path::new("bin/ffmpeg.exe"),
+                Path::new("bin/presets/x264.ffpreset")
+            ]
+        );
+        assert_eq!(fs::read(dest.join("bin/ffmpeg.exe")).unwrap(), b"ffmpeg");
+        assert_eq!(
+            fs::read(dest.join("bin/presets/x264.ffpreset")).unwrap(),
+            b"preset"
+        );
+        assert!(!dest.join("release").exists());
+        assert!(!dest.join("README.txt").exists());
+    }
 }